@@ -0,0 +1,287 @@
+//! Access to the auxiliary vector passed to the process by the Linux kernel.
+//!
+//! The auxiliary vector is read from `/proc/self/auxv` where possible. On targets where the C
+//! library provides `getauxval()`, that is used when `/proc` is unavailable.
+
+use core::mem::size_of;
+
+/// The maximum number of entries read from `/proc/self/auxv`. The kernel currently passes fewer
+/// than 40 entries on every architecture.
+const MAX_ENTRIES: usize = 64;
+
+/// A single entry from the auxiliary vector.
+///
+/// The commonly useful entry types are decoded into specific variants; all other entries are
+/// returned as [`AuxvEntry::Other`](#variant.Other).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AuxvEntry {
+    /// `AT_SECURE`: Whether the program was launched in a way that requires "secure execution".
+    Secure(bool),
+    /// `AT_UID`: The real UID of the process at `exec()` time.
+    Uid(libc::uid_t),
+    /// `AT_EUID`: The effective UID of the process at `exec()` time.
+    Euid(libc::uid_t),
+    /// `AT_GID`: The real GID of the process at `exec()` time.
+    Gid(libc::gid_t),
+    /// `AT_EGID`: The effective GID of the process at `exec()` time.
+    Egid(libc::gid_t),
+    /// `AT_EXECFN`: A pointer to the NUL-terminated filename used to execute the program.
+    ExecFn(*const libc::c_char),
+    /// `AT_RANDOM`: A pointer to 16 random bytes provided by the kernel.
+    Random(*const u8),
+    /// `AT_HWCAP`: Architecture-specific hardware capability bits.
+    HwCap(libc::c_ulong),
+    /// Any other entry, as a raw `(key, value)` pair.
+    Other(libc::c_ulong, libc::c_ulong),
+}
+
+impl AuxvEntry {
+    /// Decode an entry from its raw key and value.
+    pub fn from_raw(key: libc::c_ulong, value: libc::c_ulong) -> Self {
+        match key {
+            libc::AT_SECURE => Self::Secure(value != 0),
+            libc::AT_UID => Self::Uid(value as libc::uid_t),
+            libc::AT_EUID => Self::Euid(value as libc::uid_t),
+            libc::AT_GID => Self::Gid(value as libc::gid_t),
+            libc::AT_EGID => Self::Egid(value as libc::gid_t),
+            libc::AT_EXECFN => Self::ExecFn(value as usize as *const libc::c_char),
+            libc::AT_RANDOM => Self::Random(value as usize as *const u8),
+            libc::AT_HWCAP => Self::HwCap(value),
+            _ => Self::Other(key, value),
+        }
+    }
+
+    /// Get the raw key (`AT_*` constant) of this entry.
+    pub fn key(&self) -> libc::c_ulong {
+        match *self {
+            Self::Secure(_) => libc::AT_SECURE,
+            Self::Uid(_) => libc::AT_UID,
+            Self::Euid(_) => libc::AT_EUID,
+            Self::Gid(_) => libc::AT_GID,
+            Self::Egid(_) => libc::AT_EGID,
+            Self::ExecFn(_) => libc::AT_EXECFN,
+            Self::Random(_) => libc::AT_RANDOM,
+            Self::HwCap(_) => libc::AT_HWCAP,
+            Self::Other(key, _) => key,
+        }
+    }
+
+    /// Get the raw value of this entry.
+    pub fn value(&self) -> libc::c_ulong {
+        match *self {
+            Self::Secure(secure) => secure as libc::c_ulong,
+            Self::Uid(id) | Self::Euid(id) => id as libc::c_ulong,
+            Self::Gid(id) | Self::Egid(id) => id as libc::c_ulong,
+            Self::ExecFn(ptr) => ptr as usize as libc::c_ulong,
+            Self::Random(ptr) => ptr as usize as libc::c_ulong,
+            Self::HwCap(value) | Self::Other(_, value) => value,
+        }
+    }
+}
+
+/// An iterator over the entries in the auxiliary vector.
+///
+/// This is returned by [`entries()`](./fn.entries.html).
+#[derive(Clone, Debug)]
+pub struct Entries {
+    buf: [libc::c_ulong; MAX_ENTRIES * 2],
+    pos: usize,
+    len: usize,
+}
+
+impl Entries {
+    fn empty() -> Self {
+        Self {
+            buf: [0; MAX_ENTRIES * 2],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    fn from_proc() -> Option<Self> {
+        let mut entries = Self::empty();
+
+        let nbytes = {
+            let bytes = unsafe {
+                core::slice::from_raw_parts_mut(
+                    entries.buf.as_mut_ptr() as *mut u8,
+                    entries.buf.len() * size_of::<libc::c_ulong>(),
+                )
+            };
+            crate::util::read_file(b"/proc/self/auxv\0", bytes)?
+        };
+
+        // Only count complete entries
+        entries.len = nbytes / (size_of::<libc::c_ulong>() * 2) * 2;
+
+        Some(entries)
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        all(target_os = "android", target_pointer_width = "64"),
+    ))]
+    fn from_getauxval() -> Self {
+        let mut entries = Self::empty();
+
+        for &key in [
+            libc::AT_SECURE,
+            libc::AT_UID,
+            libc::AT_EUID,
+            libc::AT_GID,
+            libc::AT_EGID,
+            libc::AT_EXECFN,
+            libc::AT_RANDOM,
+            libc::AT_HWCAP,
+        ]
+        .iter()
+        {
+            let value = unsafe { libc::getauxval(key) };
+
+            // getauxval() returns 0 for missing entries, so we can only report AT_SECURE
+            // (for which 0 is also the default) unconditionally.
+            if value != 0 || key == libc::AT_SECURE {
+                entries.buf[entries.len] = key;
+                entries.buf[entries.len + 1] = value;
+                entries.len += 2;
+            }
+        }
+
+        entries
+    }
+}
+
+impl Iterator for Entries {
+    type Item = AuxvEntry;
+
+    fn next(&mut self) -> Option<AuxvEntry> {
+        if self.pos >= self.len {
+            return None;
+        }
+
+        let key = self.buf[self.pos];
+        let value = self.buf[self.pos + 1];
+
+        if key == libc::AT_NULL {
+            self.pos = self.len;
+            return None;
+        }
+
+        self.pos += 2;
+        Some(AuxvEntry::from_raw(key, value))
+    }
+}
+
+/// Get an iterator over the entries in the auxiliary vector.
+///
+/// This reads `/proc/self/auxv`. If that fails and the C library provides `getauxval()`, the
+/// entries decoded into specific [`AuxvEntry`] variants (other than [`AuxvEntry::Other`]) are
+/// looked up individually instead. Otherwise, the returned iterator is empty.
+///
+/// [`AuxvEntry`]: ./enum.AuxvEntry.html
+/// [`AuxvEntry::Other`]: ./enum.AuxvEntry.html#variant.Other
+#[allow(clippy::needless_return)]
+pub fn entries() -> Entries {
+    if let Some(entries) = Entries::from_proc() {
+        return entries;
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(any(
+            target_os = "linux",
+            all(target_os = "android", target_pointer_width = "64"),
+        ))] {
+            return Entries::from_getauxval();
+        } else {
+            return Entries::empty();
+        }
+    }
+}
+
+/// Look up the value of the given key (an `AT_*` constant) in the auxiliary vector.
+///
+/// Unlike the C library's `getauxval()`, this distinguishes between a missing entry (`None`) and
+/// an entry whose value is 0. It returns `None` if the auxiliary vector cannot be read at all.
+///
+/// Where the C library provides `getauxval()`, it is tried first; `/proc/self/auxv` is only
+/// consulted to disambiguate a result of 0. On 32-bit Android (where older releases lack
+/// `getauxval()`), only `/proc/self/auxv` is used.
+pub fn getauxval(key: libc::c_ulong) -> Option<libc::c_ulong> {
+    #[cfg(any(
+        target_os = "linux",
+        all(target_os = "android", target_pointer_width = "64"),
+    ))]
+    {
+        let value = unsafe { libc::getauxval(key) };
+        if value != 0 {
+            return Some(value);
+        }
+    }
+
+    let mut entries = Entries::from_proc()?;
+    entries
+        .find(|entry| entry.key() == key)
+        .map(|entry| entry.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let mut found = 0;
+
+        for entry in entries() {
+            match entry {
+                AuxvEntry::Secure(secure) => assert!(!secure),
+                AuxvEntry::Uid(uid) => assert_eq!(uid, unsafe { libc::getuid() }),
+                AuxvEntry::Euid(euid) => assert_eq!(euid, unsafe { libc::geteuid() }),
+                AuxvEntry::Gid(gid) => assert_eq!(gid, unsafe { libc::getgid() }),
+                AuxvEntry::Egid(egid) => assert_eq!(egid, unsafe { libc::getegid() }),
+                AuxvEntry::ExecFn(ptr) => assert!(unsafe { libc::strlen(ptr) } > 0),
+                AuxvEntry::Random(ptr) => assert!(!ptr.is_null()),
+                _ => continue,
+            }
+
+            found += 1;
+        }
+
+        assert!(found >= 7);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_proc_matches_getauxval() {
+        for entry in Entries::from_proc().unwrap() {
+            // glibc may adjust some entries (like AT_HWCAP), so only compare a few that it
+            // should report unmodified
+            if let AuxvEntry::Secure(_) | AuxvEntry::Uid(_) | AuxvEntry::ExecFn(_) = entry {
+                assert_eq!(unsafe { libc::getauxval(entry.key()) }, entry.value());
+            }
+        }
+
+        for entry in Entries::from_getauxval() {
+            assert_eq!(getauxval(entry.key()), Some(entry.value()));
+        }
+    }
+
+    #[test]
+    fn test_getauxval() {
+        assert_eq!(getauxval(libc::AT_SECURE), Some(0));
+        assert_eq!(
+            getauxval(libc::AT_UID),
+            Some(unsafe { libc::getuid() } as libc::c_ulong)
+        );
+        assert_eq!(getauxval(libc::AT_NULL), None);
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        for &(key, value) in [(libc::AT_SECURE, 1), (libc::AT_UID, 1000), (1000, 42)].iter() {
+            let entry = AuxvEntry::from_raw(key, value);
+            assert_eq!(entry.key(), key);
+            assert_eq!(entry.value(), value);
+        }
+    }
+}
@@ -0,0 +1,731 @@
+//! Inspection of Linux capability sets.
+//!
+//! On Linux, a program with file capabilities requires "secure execution" just like a set-UID
+//! program does. This module makes it possible to check exactly which capabilities the process
+//! holds:
+//!
+//! ```
+//! use secure_exec::capabilities::{CapSet, CapState, Capability};
+//!
+//! let state = CapState::current().unwrap();
+//!
+//! let allowed: CapSet = [Capability::NetBindService].iter().copied().collect();
+//! if !state.permitted.difference(allowed).is_empty() {
+//!     println!("holding unexpected capabilities: {:?}", state.permitted.difference(allowed));
+//! }
+//! ```
+
+use core::fmt;
+
+use crate::Errno;
+
+macro_rules! capabilities {
+    ($($(#[$attr:meta])* $variant:ident = $value:expr, $name:expr;)*) => {
+        /// A Linux capability.
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+        #[non_exhaustive]
+        #[repr(u8)]
+        pub enum Capability {
+            $($(#[$attr])* $variant = $value,)*
+        }
+
+        impl Capability {
+            /// All known capabilities, in numerical order.
+            pub const ALL: &'static [Self] = &[$(Self::$variant,)*];
+
+            /// Get the capability with the given number, or `None` if it is not known.
+            pub fn from_index(index: u8) -> Option<Self> {
+                match index {
+                    $($value => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Get the name of this capability (for example, `"CAP_CHOWN"`).
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+capabilities! {
+    /// `CAP_CHOWN`
+    Chown = 0, "CAP_CHOWN";
+    /// `CAP_DAC_OVERRIDE`
+    DacOverride = 1, "CAP_DAC_OVERRIDE";
+    /// `CAP_DAC_READ_SEARCH`
+    DacReadSearch = 2, "CAP_DAC_READ_SEARCH";
+    /// `CAP_FOWNER`
+    Fowner = 3, "CAP_FOWNER";
+    /// `CAP_FSETID`
+    Fsetid = 4, "CAP_FSETID";
+    /// `CAP_KILL`
+    Kill = 5, "CAP_KILL";
+    /// `CAP_SETGID`
+    Setgid = 6, "CAP_SETGID";
+    /// `CAP_SETUID`
+    Setuid = 7, "CAP_SETUID";
+    /// `CAP_SETPCAP`
+    Setpcap = 8, "CAP_SETPCAP";
+    /// `CAP_LINUX_IMMUTABLE`
+    LinuxImmutable = 9, "CAP_LINUX_IMMUTABLE";
+    /// `CAP_NET_BIND_SERVICE`
+    NetBindService = 10, "CAP_NET_BIND_SERVICE";
+    /// `CAP_NET_BROADCAST`
+    NetBroadcast = 11, "CAP_NET_BROADCAST";
+    /// `CAP_NET_ADMIN`
+    NetAdmin = 12, "CAP_NET_ADMIN";
+    /// `CAP_NET_RAW`
+    NetRaw = 13, "CAP_NET_RAW";
+    /// `CAP_IPC_LOCK`
+    IpcLock = 14, "CAP_IPC_LOCK";
+    /// `CAP_IPC_OWNER`
+    IpcOwner = 15, "CAP_IPC_OWNER";
+    /// `CAP_SYS_MODULE`
+    SysModule = 16, "CAP_SYS_MODULE";
+    /// `CAP_SYS_RAWIO`
+    SysRawio = 17, "CAP_SYS_RAWIO";
+    /// `CAP_SYS_CHROOT`
+    SysChroot = 18, "CAP_SYS_CHROOT";
+    /// `CAP_SYS_PTRACE`
+    SysPtrace = 19, "CAP_SYS_PTRACE";
+    /// `CAP_SYS_PACCT`
+    SysPacct = 20, "CAP_SYS_PACCT";
+    /// `CAP_SYS_ADMIN`
+    SysAdmin = 21, "CAP_SYS_ADMIN";
+    /// `CAP_SYS_BOOT`
+    SysBoot = 22, "CAP_SYS_BOOT";
+    /// `CAP_SYS_NICE`
+    SysNice = 23, "CAP_SYS_NICE";
+    /// `CAP_SYS_RESOURCE`
+    SysResource = 24, "CAP_SYS_RESOURCE";
+    /// `CAP_SYS_TIME`
+    SysTime = 25, "CAP_SYS_TIME";
+    /// `CAP_SYS_TTY_CONFIG`
+    SysTtyConfig = 26, "CAP_SYS_TTY_CONFIG";
+    /// `CAP_MKNOD`
+    Mknod = 27, "CAP_MKNOD";
+    /// `CAP_LEASE`
+    Lease = 28, "CAP_LEASE";
+    /// `CAP_AUDIT_WRITE`
+    AuditWrite = 29, "CAP_AUDIT_WRITE";
+    /// `CAP_AUDIT_CONTROL`
+    AuditControl = 30, "CAP_AUDIT_CONTROL";
+    /// `CAP_SETFCAP`
+    Setfcap = 31, "CAP_SETFCAP";
+    /// `CAP_MAC_OVERRIDE`
+    MacOverride = 32, "CAP_MAC_OVERRIDE";
+    /// `CAP_MAC_ADMIN`
+    MacAdmin = 33, "CAP_MAC_ADMIN";
+    /// `CAP_SYSLOG`
+    Syslog = 34, "CAP_SYSLOG";
+    /// `CAP_WAKE_ALARM`
+    WakeAlarm = 35, "CAP_WAKE_ALARM";
+    /// `CAP_BLOCK_SUSPEND`
+    BlockSuspend = 36, "CAP_BLOCK_SUSPEND";
+    /// `CAP_AUDIT_READ`
+    AuditRead = 37, "CAP_AUDIT_READ";
+    /// `CAP_PERFMON`
+    Perfmon = 38, "CAP_PERFMON";
+    /// `CAP_BPF`
+    Bpf = 39, "CAP_BPF";
+    /// `CAP_CHECKPOINT_RESTORE`
+    CheckpointRestore = 40, "CAP_CHECKPOINT_RESTORE";
+}
+
+impl fmt::Display for Capability {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A set of capabilities.
+///
+/// This may contain capabilities that are not known to this crate (if they are supported by the
+/// running kernel); see [`bits()`](#method.bits).
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct CapSet(u64);
+
+impl CapSet {
+    /// Returns an empty set.
+    #[inline]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Create a set from its raw bit representation (bit N represents capability N).
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Get the raw bit representation of this set.
+    #[inline]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if the set is empty.
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if the set contains the given capability.
+    #[inline]
+    pub const fn has(self, cap: Capability) -> bool {
+        self.0 & (1 << cap as u8) != 0
+    }
+
+    /// Add the given capability to the set.
+    #[inline]
+    pub fn add(&mut self, cap: Capability) {
+        self.0 |= 1 << cap as u8;
+    }
+
+    /// Remove the given capability from the set.
+    #[inline]
+    pub fn remove(&mut self, cap: Capability) {
+        self.0 &= !(1 << cap as u8);
+    }
+
+    /// Returns the capabilities that are in either set.
+    #[inline]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the capabilities that are in both sets.
+    #[inline]
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the capabilities that are in `self` but not in `other`.
+    #[inline]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Iterate over the known capabilities in this set.
+    #[inline]
+    pub fn iter(self) -> impl Iterator<Item = Capability> {
+        Capability::ALL
+            .iter()
+            .copied()
+            .filter(move |&cap| self.has(cap))
+    }
+}
+
+impl core::iter::FromIterator<Capability> for CapSet {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        let mut set = Self::empty();
+        for cap in iter {
+            set.add(cap);
+        }
+        set
+    }
+}
+
+impl fmt::Debug for CapSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// The capability sets of the current thread.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct CapState {
+    /// The permitted set.
+    pub permitted: CapSet,
+    /// The effective set.
+    pub effective: CapSet,
+    /// The inheritable set.
+    pub inheritable: CapSet,
+    /// The bounding set.
+    pub bounding: CapSet,
+    /// The ambient set (always empty on kernels older than 4.3).
+    pub ambient: CapSet,
+}
+
+impl CapState {
+    /// Get the capability sets of the current thread.
+    ///
+    /// This uses `capget()` and `prctl()`; if `capget()` fails, it falls back on parsing
+    /// `/proc/self/status`.
+    pub fn current() -> Result<Self, Errno> {
+        match capget() {
+            Ok((permitted, effective, inheritable)) => Ok(Self {
+                permitted,
+                effective,
+                inheritable,
+                bounding: read_bounding(),
+                ambient: read_ambient(),
+            }),
+
+            Err(err) => Self::from_proc_status().ok_or(err),
+        }
+    }
+
+    fn from_proc_status() -> Option<Self> {
+        let mut buf = [0; 4096];
+        let len = crate::util::read_file(b"/proc/thread-self/status\0", &mut buf)
+            .or_else(|| crate::util::read_file(b"/proc/self/status\0", &mut buf))?;
+
+        let mut state = Self::default();
+        let mut found = 0;
+
+        for line in buf[..len].split(|&ch| ch == b'\n') {
+            let field = match line.iter().position(|&ch| ch == b':') {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let set = match &line[..field] {
+                b"CapPrm" => &mut state.permitted,
+                b"CapEff" => &mut state.effective,
+                b"CapInh" => &mut state.inheritable,
+                b"CapBnd" => &mut state.bounding,
+                b"CapAmb" => &mut state.ambient,
+                _ => continue,
+            };
+
+            *set = CapSet(parse_hex(&line[field + 1..])?);
+            found += 1;
+        }
+
+        // CapAmb is missing on older kernels
+        if found >= 4 {
+            Some(state)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    let s = core::str::from_utf8(s).ok()?.trim();
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[inline]
+fn combine(lo: u32, hi: u32) -> CapSet {
+    CapSet(((hi as u64) << 32) | lo as u64)
+}
+
+/// Get the (permitted, effective, inheritable) capability sets of the current thread.
+pub(crate) fn capget() -> Result<(CapSet, CapSet, CapSet), Errno> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        return Err(Errno::last());
+    }
+
+    Ok((
+        combine(data[0].permitted, data[1].permitted),
+        combine(data[0].effective, data[1].effective),
+        combine(data[0].inheritable, data[1].inheritable),
+    ))
+}
+
+/// Set the (permitted, effective, inheritable) capability sets of the current thread.
+fn capset(permitted: CapSet, effective: CapSet, inheritable: CapSet) -> Result<(), Errno> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+
+    let mut data = [CapUserData::default(); 2];
+    for (i, item) in data.iter_mut().enumerate() {
+        let shift = i * 32;
+        item.permitted = (permitted.0 >> shift) as u32;
+        item.effective = (effective.0 >> shift) as u32;
+        item.inheritable = (inheritable.0 >> shift) as u32;
+    }
+
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(Errno::last());
+    }
+
+    Ok(())
+}
+
+fn read_bounding() -> CapSet {
+    let mut set = CapSet::empty();
+
+    for cap in 0..64 {
+        match unsafe { libc::prctl(libc::PR_CAPBSET_READ, cap as libc::c_ulong, 0, 0, 0) } {
+            1 => set.0 |= 1 << cap,
+            0 => (),
+            // EINVAL means we've gone past the last capability supported by the kernel
+            _ => break,
+        }
+    }
+
+    set
+}
+
+fn read_ambient() -> CapSet {
+    let mut set = CapSet::empty();
+
+    for cap in 0..64 {
+        match unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_IS_SET as libc::c_ulong,
+                cap as libc::c_ulong,
+                0,
+                0,
+            )
+        } {
+            1 => set.0 |= 1 << cap,
+            0 => (),
+            // EINVAL means either we've gone past the last capability, or the kernel doesn't
+            // support ambient capabilities
+            _ => break,
+        }
+    }
+
+    set
+}
+
+// These are defined in <linux/securebits.h>
+const SECBIT_NOROOT: libc::c_ulong = 1 << 0;
+const SECBIT_NOROOT_LOCKED: libc::c_ulong = 1 << 1;
+const SECBIT_NO_SETUID_FIXUP: libc::c_ulong = 1 << 2;
+const SECBIT_NO_SETUID_FIXUP_LOCKED: libc::c_ulong = 1 << 3;
+
+/// Options for [`retain_capabilities_with()`](./fn.retain_capabilities_with.html).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RetainOptions {
+    /// Also remove capabilities that are not being kept from the bounding set. This requires
+    /// `CAP_SETPCAP`, and it prevents the capabilities from being regained even by executing a
+    /// program with file capabilities. (Default: `false`.)
+    pub bounding: bool,
+    /// Set and lock the `SECBIT_NOROOT` and `SECBIT_NO_SETUID_FIXUP` securebits, so that the
+    /// dropped capabilities cannot be regained by executing a set-UID-root program or through a
+    /// UID change. This requires `CAP_SETPCAP`; if it is not in the effective set, the other
+    /// capabilities are still dropped, but [`RetainError::SecurebitsSkipped`] is returned.
+    /// (Default: `true`.)
+    ///
+    /// [`RetainError::SecurebitsSkipped`]: ./enum.RetainError.html#variant.SecurebitsSkipped
+    pub securebits: bool,
+}
+
+impl Default for RetainOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            bounding: false,
+            securebits: true,
+        }
+    }
+}
+
+/// An error returned by [`retain_capabilities()`] and [`retain_capabilities_with()`].
+///
+/// [`retain_capabilities()`]: ./fn.retain_capabilities.html
+/// [`retain_capabilities_with()`]: ./fn.retain_capabilities_with.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RetainError {
+    /// A system call failed.
+    Os {
+        /// The name of the operation that failed (for example, `"capset"`).
+        op: &'static str,
+        /// The error it failed with.
+        errno: Errno,
+    },
+    /// The given capabilities were still present in one of the sets after the operation.
+    NotCleared(CapSet),
+    /// The securebits could not be set because `CAP_SETPCAP` was not in the effective set. All
+    /// other steps were still performed.
+    SecurebitsSkipped,
+}
+
+impl fmt::Display for RetainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Os { op, errno } => write!(f, "{}() failed: {}", op, errno),
+            Self::NotCleared(caps) => write!(f, "could not clear capabilities: {:?}", caps),
+            Self::SecurebitsSkipped => {
+                f.write_str("could not set securebits: CAP_SETPCAP is not in the effective set")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RetainError {}
+
+/// Drop every capability except the ones in `keep`, using the default [`RetainOptions`].
+///
+/// See [`retain_capabilities_with()`] for details.
+///
+/// [`RetainOptions`]: ./struct.RetainOptions.html
+/// [`retain_capabilities_with()`]: ./fn.retain_capabilities_with.html
+#[inline]
+pub fn retain_capabilities(keep: &[Capability]) -> Result<(), RetainError> {
+    retain_capabilities_with(keep, RetainOptions::default())
+}
+
+/// Drop every capability except the ones in `keep`.
+///
+/// This performs the following steps, in order:
+///
+/// 1. If `options.securebits` is set and `CAP_SETPCAP` is in the effective set, set and lock the
+///    `SECBIT_NOROOT` and `SECBIT_NO_SETUID_FIXUP` securebits.
+/// 2. If `options.bounding` is set, remove every other capability from the bounding set.
+/// 3. Remove every other capability from the ambient set.
+/// 4. Remove every other capability from the permitted, effective, and inheritable sets.
+/// 5. Verify that no other capabilities remain in any of the affected sets; if any do,
+///    [`RetainError::NotCleared`] is returned with the capabilities that are left.
+///
+/// If setting the securebits fails (or is skipped because `CAP_SETPCAP` is not in the effective
+/// set, in which case [`RetainError::SecurebitsSkipped`] is returned), the remaining steps are
+/// still performed (so the capabilities are dropped either way), and then the error is returned.
+///
+/// Capabilities in `keep` that the process does not currently hold are not added.
+///
+/// Note that capability sets are per-thread; this only affects the calling thread (and threads
+/// it creates afterward). It should be called before spawning any other threads.
+///
+/// [`RetainError::NotCleared`]: ./enum.RetainError.html#variant.NotCleared
+/// [`RetainError::SecurebitsSkipped`]: ./enum.RetainError.html#variant.SecurebitsSkipped
+pub fn retain_capabilities_with(
+    keep: &[Capability],
+    options: RetainOptions,
+) -> Result<(), RetainError> {
+    let keep: CapSet = keep.iter().copied().collect();
+
+    let (permitted, effective, inheritable) = capget().map_err(|errno| RetainError::Os {
+        op: "capget",
+        errno,
+    })?;
+
+    // Don't bail out yet if this fails; the capabilities should still be dropped
+    let securebits_res = if !options.securebits {
+        Ok(())
+    } else if effective.has(Capability::Setpcap) {
+        set_securebits()
+    } else {
+        Err(RetainError::SecurebitsSkipped)
+    };
+
+    if options.bounding {
+        // Failures are detected during verification
+        for cap in 0..64 {
+            if keep.0 & (1 << cap) == 0 {
+                unsafe {
+                    libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0);
+                }
+            }
+        }
+    }
+
+    let ambient = read_ambient().difference(keep);
+    for cap in 0..64 {
+        if ambient.0 & (1 << cap) != 0 {
+            unsafe {
+                libc::prctl(
+                    libc::PR_CAP_AMBIENT,
+                    libc::PR_CAP_AMBIENT_LOWER as libc::c_ulong,
+                    cap as libc::c_ulong,
+                    0,
+                    0,
+                );
+            }
+        }
+    }
+
+    capset(
+        permitted.intersection(keep),
+        effective.intersection(keep),
+        inheritable.intersection(keep),
+    )
+    .map_err(|errno| RetainError::Os {
+        op: "capset",
+        errno,
+    })?;
+
+    let state = CapState::current().map_err(|errno| RetainError::Os {
+        op: "capget",
+        errno,
+    })?;
+
+    let mut remaining = state
+        .permitted
+        .union(state.effective)
+        .union(state.inheritable)
+        .union(state.ambient);
+    if options.bounding {
+        remaining = remaining.union(state.bounding);
+    }
+
+    let remaining = remaining.difference(keep);
+    if remaining.is_empty() {
+        securebits_res
+    } else {
+        Err(RetainError::NotCleared(remaining))
+    }
+}
+
+fn set_securebits() -> Result<(), RetainError> {
+    let bits = unsafe { libc::prctl(libc::PR_GET_SECUREBITS, 0, 0, 0, 0) };
+    if bits < 0 {
+        return Err(RetainError::Os {
+            op: "prctl(PR_GET_SECUREBITS)",
+            errno: Errno::last(),
+        });
+    }
+
+    let bits = bits as libc::c_ulong
+        | SECBIT_NOROOT
+        | SECBIT_NOROOT_LOCKED
+        | SECBIT_NO_SETUID_FIXUP
+        | SECBIT_NO_SETUID_FIXUP_LOCKED;
+
+    if unsafe { libc::prctl(libc::PR_SET_SECUREBITS, bits, 0, 0, 0) } != 0 {
+        return Err(RetainError::Os {
+            op: "prctl(PR_SET_SECUREBITS)",
+            errno: Errno::last(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability() {
+        for (i, &cap) in Capability::ALL.iter().enumerate() {
+            assert_eq!(cap as usize, i);
+            assert_eq!(Capability::from_index(i as u8), Some(cap));
+        }
+        assert_eq!(Capability::from_index(Capability::ALL.len() as u8), None);
+
+        assert_eq!(Capability::NetBindService.name(), "CAP_NET_BIND_SERVICE");
+    }
+
+    #[test]
+    fn test_capset() {
+        let mut set: CapSet = [Capability::Chown, Capability::Kill]
+            .iter()
+            .copied()
+            .collect();
+        assert!(set.has(Capability::Chown));
+        assert!(!set.has(Capability::Setuid));
+
+        set.remove(Capability::Chown);
+        set.add(Capability::CheckpointRestore);
+        assert_eq!(set.bits(), (1 << 5) | (1 << 40));
+        assert_eq!(
+            set.iter().collect::<std::vec::Vec<_>>(),
+            [Capability::Kill, Capability::CheckpointRestore]
+        );
+
+        let other = CapSet::from_bits(1 << 5);
+        assert_eq!(set.difference(other), CapSet::from_bits(1 << 40));
+        assert_eq!(set.intersection(other), other);
+        assert_eq!(other.union(set), set);
+    }
+
+    #[test]
+    fn test_current_matches_proc() {
+        let state = CapState::current().unwrap();
+        let proc_state = CapState::from_proc_status().unwrap();
+
+        assert_eq!(state.permitted, proc_state.permitted);
+        assert_eq!(state.effective, proc_state.effective);
+        assert_eq!(state.inheritable, proc_state.inheritable);
+        assert_eq!(state.bounding, proc_state.bounding);
+        assert_eq!(state.ambient, proc_state.ambient);
+    }
+
+    #[test]
+    fn test_retain_capabilities() {
+        let options = RetainOptions {
+            bounding: false,
+            securebits: false,
+        };
+
+        assert!(crate::util::run_in_child(|| {
+            retain_capabilities_with(&[], options).is_ok() && {
+                let state = CapState::current().unwrap();
+                state.permitted.is_empty() && state.effective.is_empty()
+            }
+        }));
+
+        let state = CapState::current().unwrap();
+        if state.permitted.has(Capability::NetBindService) {
+            assert!(crate::util::run_in_child(|| {
+                retain_capabilities_with(&[Capability::NetBindService], options).is_ok()
+                    && CapState::current().unwrap().permitted
+                        == CapSet::from_bits(1 << Capability::NetBindService as u8)
+            }));
+        }
+
+        if state.effective.has(Capability::Setpcap) && state.permitted.has(Capability::Kill) {
+            assert!(crate::util::run_in_child(|| {
+                // Without CAP_SETPCAP, the securebits are skipped (and this is reported), but
+                // everything else is dropped
+                let (mut permitted, mut effective, inheritable) = capget().unwrap();
+                permitted.remove(Capability::Setpcap);
+                effective.remove(Capability::Setpcap);
+                capset(permitted, effective, inheritable).unwrap();
+
+                retain_capabilities(&[Capability::Kill]) == Err(RetainError::SecurebitsSkipped) && {
+                    let state = CapState::current().unwrap();
+                    let bits = unsafe { libc::prctl(libc::PR_GET_SECUREBITS, 0, 0, 0, 0) };
+                    state.permitted == CapSet::from_bits(1 << Capability::Kill as u8)
+                        && bits as libc::c_ulong & SECBIT_NOROOT_LOCKED == 0
+                }
+            }));
+        }
+
+        if state.effective.has(Capability::Setpcap) {
+            assert!(crate::util::run_in_child(|| {
+                let options = RetainOptions {
+                    bounding: true,
+                    securebits: true,
+                };
+
+                retain_capabilities_with(&[Capability::Kill], options).is_ok() && {
+                    let state = CapState::current().unwrap();
+                    let bits = unsafe { libc::prctl(libc::PR_GET_SECUREBITS, 0, 0, 0, 0) };
+                    state
+                        .bounding
+                        .difference(CapSet::from_bits(1 << 5))
+                        .is_empty()
+                        && bits as libc::c_ulong & SECBIT_NOROOT_LOCKED != 0
+                }
+            }));
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// A snapshot of the process's user and group IDs.
+///
+/// Use [`Credentials::current()`] to take a new snapshot, or [`initial_credentials()`] to get the
+/// (cached) snapshot that was taken the first time it was called.
+///
+/// On platforms without `getresuid()`/`getresgid()` (for example, macOS, NetBSD, and
+/// Solaris/Illumos), the saved set-user-ID and set-group-ID cannot be queried; they are reported as
+/// equal to the effective UID/GID (which is what they are set to by `execve()`).
+///
+/// [`Credentials::current()`]: #method.current
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Credentials {
+    ruid: libc::uid_t,
+    euid: libc::uid_t,
+    suid: libc::uid_t,
+    rgid: libc::gid_t,
+    egid: libc::gid_t,
+    sgid: libc::gid_t,
+    #[cfg(feature = "std")]
+    groups: Vec<libc::gid_t>,
+}
+
+impl Credentials {
+    /// Take a snapshot of the current process's credentials.
+    pub fn current() -> Self {
+        let (ruid, euid, suid) = getresuid();
+        let (rgid, egid, sgid) = getresgid();
+
+        Self {
+            ruid,
+            euid,
+            suid,
+            rgid,
+            egid,
+            sgid,
+            #[cfg(feature = "std")]
+            groups: getgroups(),
+        }
+    }
+
+    /// Get the real user ID.
+    #[inline]
+    pub fn ruid(&self) -> libc::uid_t {
+        self.ruid
+    }
+
+    /// Get the effective user ID.
+    #[inline]
+    pub fn euid(&self) -> libc::uid_t {
+        self.euid
+    }
+
+    /// Get the saved set-user-ID.
+    #[inline]
+    pub fn suid(&self) -> libc::uid_t {
+        self.suid
+    }
+
+    /// Get the real group ID.
+    #[inline]
+    pub fn rgid(&self) -> libc::gid_t {
+        self.rgid
+    }
+
+    /// Get the effective group ID.
+    #[inline]
+    pub fn egid(&self) -> libc::gid_t {
+        self.egid
+    }
+
+    /// Get the saved set-group-ID.
+    #[inline]
+    pub fn sgid(&self) -> libc::gid_t {
+        self.sgid
+    }
+
+    /// Get the supplementary group IDs.
+    ///
+    /// Note that on some platforms (for example, macOS and the BSDs), this list may include the
+    /// effective GID.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn groups(&self) -> &[libc::gid_t] {
+        &self.groups
+    }
+}
+
+#[allow(clippy::needless_return)]
+pub(crate) fn getresuid() -> (libc::uid_t, libc::uid_t, libc::uid_t) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+        ))] {
+            let mut ruid = 0;
+            let mut euid = 0;
+            let mut suid = 0;
+            unsafe {
+                libc::getresuid(&mut ruid, &mut euid, &mut suid);
+            }
+            return (ruid, euid, suid);
+        } else {
+            let euid = unsafe { libc::geteuid() };
+            return (unsafe { libc::getuid() }, euid, euid);
+        }
+    }
+}
+
+#[allow(clippy::needless_return)]
+pub(crate) fn getresgid() -> (libc::gid_t, libc::gid_t, libc::gid_t) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+        ))] {
+            let mut rgid = 0;
+            let mut egid = 0;
+            let mut sgid = 0;
+            unsafe {
+                libc::getresgid(&mut rgid, &mut egid, &mut sgid);
+            }
+            return (rgid, egid, sgid);
+        } else {
+            let egid = unsafe { libc::getegid() };
+            return (unsafe { libc::getgid() }, egid, egid);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn getgroups() -> Vec<libc::gid_t> {
+    loop {
+        let n = unsafe { libc::getgroups(0, core::ptr::null_mut()) };
+        if n <= 0 {
+            return Vec::new();
+        }
+
+        let mut groups = vec![0; n as usize];
+        let n = unsafe { libc::getgroups(n, groups.as_mut_ptr()) };
+        if n >= 0 {
+            groups.truncate(n as usize);
+            return groups;
+        }
+        // The list grew in between the calls; try again
+    }
+}
+
+const UNINIT: u8 = 0;
+const BUSY: u8 = 1;
+const READY: u8 = 2;
+
+struct CredentialsCache {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<Credentials>>,
+}
+
+// SAFETY: `value` is only written once, by the thread that moves `state` from UNINIT to BUSY, and
+// it is only read after `state` has been set to READY.
+unsafe impl Sync for CredentialsCache {}
+
+static CACHE: CredentialsCache = CredentialsCache {
+    state: AtomicU8::new(UNINIT),
+    value: UnsafeCell::new(MaybeUninit::uninit()),
+};
+
+/// Resets `CACHE.state` to `UNINIT` when dropped.
+struct ResetOnUnwind;
+
+impl Drop for ResetOnUnwind {
+    #[inline]
+    fn drop(&mut self) {
+        CACHE.state.store(UNINIT, Ordering::SeqCst);
+    }
+}
+
+/// Get a snapshot of the process's credentials, taken the first time this function was called.
+///
+/// This allows code that later changes the process's UIDs/GIDs (for example, to drop privileges)
+/// to still determine who originally launched the program.
+///
+/// As with [`is_secure()`], this function should be called for the first time **before** taking
+/// any action that could change the process's credentials; in binary crates, it's recommended to
+/// call it at the start of `main()`.
+///
+/// Unlike [`is_secure()`], if this function is called concurrently from multiple threads for the
+/// first time, exactly one of them will take the snapshot and the others will wait for it to
+/// finish. All callers are guaranteed to see the same snapshot.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+pub fn initial_credentials() -> &'static Credentials {
+    loop {
+        match CACHE
+            .state
+            .compare_exchange(UNINIT, BUSY, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => {
+                // If taking the snapshot panics, let another caller try again instead of leaving
+                // it spinning forever
+                let guard = ResetOnUnwind;
+                let creds = Credentials::current();
+                unsafe {
+                    (*CACHE.value.get()).as_mut_ptr().write(creds);
+                }
+                core::mem::forget(guard);
+                CACHE.state.store(READY, Ordering::SeqCst);
+                break;
+            }
+
+            Err(READY) => break,
+
+            // Another thread is taking the snapshot
+            Err(_) => core::hint::spin_loop(),
+        }
+    }
+
+    unsafe { &*(*CACHE.value.get()).as_ptr() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_credentials() {
+        let creds = Credentials::current();
+
+        unsafe {
+            assert_eq!(creds.ruid(), libc::getuid());
+            assert_eq!(creds.euid(), libc::geteuid());
+            assert_eq!(creds.rgid(), libc::getgid());
+            assert_eq!(creds.egid(), libc::getegid());
+        }
+
+        assert_eq!(creds.suid(), creds.euid());
+        assert_eq!(creds.sgid(), creds.egid());
+    }
+
+    #[test]
+    fn test_initial_credentials() {
+        let creds = initial_credentials();
+        assert_eq!(creds, &Credentials::current());
+        assert!(core::ptr::eq(creds, initial_credentials()));
+    }
+}
@@ -0,0 +1,436 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+/// Environment variables that glibc removes from the environment of programs that require
+/// "secure execution" (the `UNSECURE_ENVVARS` list).
+pub const GLIBC_UNSECURE_ENVVARS: &[&str] = &[
+    "GCONV_PATH",
+    "GETCONF_DIR",
+    "GLIBC_TUNABLES",
+    "HOSTALIASES",
+    "LD_AUDIT",
+    "LD_DEBUG",
+    "LD_DEBUG_OUTPUT",
+    "LD_DYNAMIC_WEAK",
+    "LD_HWCAP_MASK",
+    "LD_LIBRARY_PATH",
+    "LD_ORIGIN_PATH",
+    "LD_PRELOAD",
+    "LD_PROFILE",
+    "LD_SHOW_AUXV",
+    "LD_USE_LOAD_BIAS",
+    "LOCALDOMAIN",
+    "LOCPATH",
+    "MALLOC_ARENA_MAX",
+    "MALLOC_ARENA_TEST",
+    "MALLOC_CHECK_",
+    "MALLOC_MMAP_MAX_",
+    "MALLOC_MMAP_THRESHOLD_",
+    "MALLOC_PERTURB_",
+    "MALLOC_TCACHE_COUNT",
+    "MALLOC_TCACHE_MAX",
+    "MALLOC_TCACHE_UNSORTED_LIMIT",
+    "MALLOC_TOP_PAD_",
+    "MALLOC_TRACE",
+    "MALLOC_TRIM_THRESHOLD_",
+    "NIS_PATH",
+    "NLSPATH",
+    "RESOLV_HOST_CONF",
+    "RES_OPTIONS",
+    "TMPDIR",
+    "TZDIR",
+];
+
+/// Prefixes of environment variables that are removed in addition to
+/// [`GLIBC_UNSECURE_ENVVARS`], since other dynamic linkers and allocators (and newer glibc
+/// releases) recognize further variables with these prefixes.
+///
+/// [`GLIBC_UNSECURE_ENVVARS`]: ./constant.GLIBC_UNSECURE_ENVVARS.html
+pub const UNSECURE_ENVVAR_PREFIXES: &[&str] = &["LD_", "MALLOC_", "DYLD_"];
+
+/// Environment variables that alter the behavior of Rust programs (or commonly used Rust
+/// libraries) and should not be trusted when the program requires "secure execution".
+pub const RUST_UNSECURE_ENVVARS: &[&str] = &[
+    "RUST_BACKTRACE",
+    "RUST_LIB_BACKTRACE",
+    "RUST_LOG",
+    "RUST_LOG_STYLE",
+    "RUST_MIN_STACK",
+];
+
+/// A report of the changes made by [`sanitize_environment()`].
+///
+/// [`sanitize_environment()`]: ./fn.sanitize_environment.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EnvReport {
+    removed: Vec<OsString>,
+}
+
+impl EnvReport {
+    /// Get the names of the environment variables that were removed.
+    #[inline]
+    pub fn removed(&self) -> &[OsString] {
+        &self.removed
+    }
+
+    /// Returns `true` if no environment variables were removed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+}
+
+fn is_unsecure_envvar(name: &OsStr) -> bool {
+    // Compare bytes so that names with a non-Unicode tail (like "LD_\xff") still match prefixes
+    let name = name.as_bytes();
+
+    GLIBC_UNSECURE_ENVVARS
+        .iter()
+        .chain(RUST_UNSECURE_ENVVARS.iter())
+        .any(|var| var.as_bytes() == name)
+        || UNSECURE_ENVVAR_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_bytes()))
+}
+
+/// Remove environment variables that should not be trusted from the current process's environment
+/// (if the program requires "secure execution").
+///
+/// If [`is_secure()`] returns `true`, this removes every variable in
+/// [`GLIBC_UNSECURE_ENVVARS`] and [`RUST_UNSECURE_ENVVARS`], as well as every variable whose name
+/// starts with one of the [`UNSECURE_ENVVAR_PREFIXES`]. Otherwise, it does nothing.
+///
+/// Since this modifies the process's environment, it should be called early in `main()`, before
+/// any other threads are spawned.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`GLIBC_UNSECURE_ENVVARS`]: ./constant.GLIBC_UNSECURE_ENVVARS.html
+/// [`RUST_UNSECURE_ENVVARS`]: ./constant.RUST_UNSECURE_ENVVARS.html
+/// [`UNSECURE_ENVVAR_PREFIXES`]: ./constant.UNSECURE_ENVVAR_PREFIXES.html
+pub fn sanitize_environment() -> EnvReport {
+    if crate::is_secure() {
+        sanitize_environment_unconditional()
+    } else {
+        EnvReport::default()
+    }
+}
+
+pub(crate) fn sanitize_environment_unconditional() -> EnvReport {
+    let removed: Vec<OsString> = std::env::vars_os()
+        .map(|(name, _)| name)
+        .filter(|name| is_unsecure_envvar(name))
+        .collect();
+
+    for name in removed.iter() {
+        std::env::remove_var(name);
+    }
+
+    EnvReport { removed }
+}
+
+type Validator = Box<dyn Fn(&OsStr) -> bool + Send + Sync>;
+
+/// An allowlist-based view of the process's environment.
+///
+/// If [`is_secure()`] returns `true`, only variables that have been explicitly allowed (by exact
+/// name, by prefix, or by name with a validator that accepts the value) are visible through this
+/// view. Otherwise, every variable is visible, and the methods behave like their equivalents in
+/// `std::env`.
+///
+/// ```
+/// let env = secure_exec::SecureEnv::new()
+///     .allow("TERM")
+///     .allow("LANG")
+///     .allow_prefix("LC_")
+///     .allow_if("TZ", |value| !value.is_empty());
+///
+/// let term = env.get_os("TERM");
+/// ```
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+pub struct SecureEnv {
+    active: bool,
+    names: Vec<OsString>,
+    prefixes: Vec<String>,
+    validators: Vec<(OsString, Validator)>,
+}
+
+impl SecureEnv {
+    /// Create a new view with an empty allowlist.
+    pub fn new() -> Self {
+        Self {
+            active: crate::is_secure(),
+            names: Vec::new(),
+            prefixes: Vec::new(),
+            validators: Vec::new(),
+        }
+    }
+
+    /// Allow the variable with the given name.
+    pub fn allow<K: Into<OsString>>(mut self, name: K) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Allow all variables whose names start with the given prefix.
+    pub fn allow_prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Allow the variable with the given name, but only if `validator` returns `true` for its
+    /// value.
+    ///
+    /// If the same name is also allowed with [`allow()`](#method.allow) or
+    /// [`allow_prefix()`](#method.allow_prefix), the validator still applies.
+    pub fn allow_if<K, F>(mut self, name: K, validator: F) -> Self
+    where
+        K: Into<OsString>,
+        F: Fn(&OsStr) -> bool + Send + Sync + 'static,
+    {
+        self.validators.push((name.into(), Box::new(validator)));
+        self
+    }
+
+    /// Check whether the variable with the given name and value passes the allowlist.
+    ///
+    /// This ignores whether the process requires "secure execution".
+    pub fn is_allowed(&self, name: &OsStr, value: &OsStr) -> bool {
+        let mut validated = false;
+
+        for (vname, validator) in self.validators.iter() {
+            if vname == name {
+                if !validator(value) {
+                    return false;
+                }
+                validated = true;
+            }
+        }
+
+        validated
+            || self.names.iter().any(|n| n == name)
+            || name.to_str().is_some_and(|name| {
+                self.prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+            })
+    }
+
+    /// Get the specified environmental variable, if it is visible through this view.
+    ///
+    /// This is equivalent to `std::env::var()`, except that it returns a "not found" error if the
+    /// process requires "secure execution" and the variable is not allowed.
+    pub fn get<K: AsRef<OsStr>>(&self, key: K) -> Result<String, std::env::VarError> {
+        match self.get_os(key) {
+            Some(value) => value.into_string().map_err(std::env::VarError::NotUnicode),
+            None => Err(std::env::VarError::NotPresent),
+        }
+    }
+
+    /// Get the specified environmental variable, if it is visible through this view.
+    ///
+    /// This is equivalent to `std::env::var_os()`, except that it returns `None` if the process
+    /// requires "secure execution" and the variable is not allowed.
+    pub fn get_os<K: AsRef<OsStr>>(&self, key: K) -> Option<OsString> {
+        let key = key.as_ref();
+        let value = std::env::var_os(key)?;
+
+        if !self.active || self.is_allowed(key, &value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over all of the environmental variables visible through this view.
+    ///
+    /// This is equivalent to `std::env::vars_os()`, except that if the process requires "secure
+    /// execution" only allowed variables are returned.
+    pub fn vars_os(&self) -> VarsOs<'_> {
+        VarsOs {
+            inner: std::env::vars_os(),
+            env: self,
+        }
+    }
+
+    /// Remove every variable that is not visible through this view from the process's
+    /// environment.
+    ///
+    /// If the process does not require "secure execution", this does nothing.
+    ///
+    /// Since this modifies the process's environment, it should be called early in `main()`,
+    /// before any other threads are spawned.
+    pub fn apply(&self) -> EnvReport {
+        if !self.active {
+            return EnvReport::default();
+        }
+
+        let removed: Vec<OsString> = std::env::vars_os()
+            .filter(|(name, value)| !self.is_allowed(name, value))
+            .map(|(name, _)| name)
+            .collect();
+
+        for name in removed.iter() {
+            std::env::remove_var(name);
+        }
+
+        EnvReport { removed }
+    }
+}
+
+impl Default for SecureEnv {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for SecureEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SecureEnv")
+            .field("active", &self.active)
+            .field("names", &self.names)
+            .field("prefixes", &self.prefixes)
+            .field(
+                "validators",
+                &self
+                    .validators
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// An iterator over the environmental variables visible through a [`SecureEnv`].
+///
+/// This is returned by [`SecureEnv::vars_os()`].
+///
+/// [`SecureEnv`]: ./struct.SecureEnv.html
+/// [`SecureEnv::vars_os()`]: ./struct.SecureEnv.html#method.vars_os
+pub struct VarsOs<'a> {
+    inner: std::env::VarsOs,
+    env: &'a SecureEnv,
+}
+
+impl Iterator for VarsOs<'_> {
+    type Item = (OsString, OsString);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let env = self.env;
+
+        self.inner
+            .by_ref()
+            .find(|(name, value)| !env.active || env.is_allowed(name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_is_unsecure_envvar() {
+        for name in [
+            "LD_PRELOAD",
+            "LD_FOO",
+            "MALLOC_CHECK_",
+            "TMPDIR",
+            "RUST_LOG",
+        ]
+        .iter()
+        {
+            assert!(is_unsecure_envvar(OsStr::new(name)), "{}", name);
+        }
+
+        for name in ["PATH", "HOME", "TERM", "LANG", "OLD_PRELOAD"].iter() {
+            assert!(!is_unsecure_envvar(OsStr::new(name)), "{}", name);
+        }
+
+        assert!(is_unsecure_envvar(OsStr::from_bytes(b"LD_\xff")));
+        assert!(!is_unsecure_envvar(OsStr::from_bytes(b"PATH\xff")));
+    }
+
+    #[test]
+    fn test_sanitize_environment() {
+        assert!(sanitize_environment().is_empty());
+
+        assert!(run_in_child(|| {
+            std::env::set_var("SECURE_EXEC_TEST_KEEP", "1");
+            std::env::set_var("LD_SECURE_EXEC_TEST", "1");
+
+            let report = sanitize_environment_unconditional();
+
+            report
+                .removed()
+                .iter()
+                .any(|name| name == "LD_SECURE_EXEC_TEST")
+                && std::env::var_os("LD_SECURE_EXEC_TEST").is_none()
+                && std::env::var_os("SECURE_EXEC_TEST_KEEP").is_some()
+        }));
+    }
+
+    #[test]
+    fn test_secure_env_allowlist() {
+        let env = SecureEnv::new()
+            .allow("TERM")
+            .allow_prefix("LC_")
+            .allow_if("TZ", |value| !value.is_empty());
+
+        let allowed = |name: &str, value: &str| env.is_allowed(OsStr::new(name), OsStr::new(value));
+
+        assert!(allowed("TERM", "xterm"));
+        assert!(allowed("LC_ALL", "C"));
+        assert!(allowed("TZ", "UTC"));
+        assert!(!allowed("TZ", ""));
+        assert!(!allowed("PATH", "/bin"));
+        assert!(!allowed("XLC_ALL", "C"));
+    }
+
+    #[test]
+    fn test_secure_env() {
+        assert!(run_in_child(|| {
+            std::env::set_var("SECURE_EXEC_TEST_ENV_A", "a");
+            std::env::set_var("SECURE_EXEC_TEST_ENV_B", "b");
+
+            let mut env = SecureEnv::new().allow("SECURE_EXEC_TEST_ENV_A");
+
+            // Not secure; everything is visible
+            assert_eq!(env.get("SECURE_EXEC_TEST_ENV_B").unwrap(), "b");
+            assert!(env
+                .vars_os()
+                .any(|(name, _)| name == "SECURE_EXEC_TEST_ENV_B"));
+            assert!(env.apply().is_empty());
+            assert!(std::env::var_os("SECURE_EXEC_TEST_ENV_B").is_some());
+
+            env.active = true;
+
+            assert_eq!(env.get("SECURE_EXEC_TEST_ENV_A").unwrap(), "a");
+            assert_eq!(env.get_os("SECURE_EXEC_TEST_ENV_B"), None);
+            assert_eq!(
+                env.get("SECURE_EXEC_TEST_ENV_B"),
+                Err(std::env::VarError::NotPresent)
+            );
+            assert!(env
+                .vars_os()
+                .all(|(name, _)| name == "SECURE_EXEC_TEST_ENV_A"));
+
+            let report = env.apply();
+
+            report
+                .removed()
+                .iter()
+                .any(|name| name == "SECURE_EXEC_TEST_ENV_B")
+                && !report
+                    .removed()
+                    .iter()
+                    .any(|name| name == "SECURE_EXEC_TEST_ENV_A")
+                && std::env::vars_os()
+                    .map(|(name, _)| name)
+                    .eq(core::iter::once(OsString::from("SECURE_EXEC_TEST_ENV_A")))
+        }));
+    }
+}
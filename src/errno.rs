@@ -0,0 +1,48 @@
+use core::fmt;
+
+/// An OS error code (an `errno` value).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Errno(libc::c_int);
+
+impl Errno {
+    /// Get the current thread's last OS error.
+    #[inline]
+    pub fn last() -> Self {
+        Self(crate::util::errno())
+    }
+
+    /// Create an `Errno` from a raw `errno` value.
+    #[inline]
+    pub const fn from_raw(code: libc::c_int) -> Self {
+        Self(code)
+    }
+
+    /// Get the raw `errno` value.
+    #[inline]
+    pub const fn raw(self) -> libc::c_int {
+        self.0
+    }
+}
+
+impl fmt::Display for Errno {
+    #[cfg(feature = "std")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        std::io::Error::from_raw_os_error(self.0).fmt(f)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OS error {}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Errno {}
+
+#[cfg(feature = "std")]
+impl From<Errno> for std::io::Error {
+    #[inline]
+    fn from(err: Errno) -> Self {
+        Self::from_raw_os_error(err.0)
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{Apply, Errno};
+
+/// A report of the changes made by [`ensure_std_fds()`].
+///
+/// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct StdFdsReport {
+    reopened: [bool; 3],
+}
+
+impl StdFdsReport {
+    /// Returns `true` if the given standard file descriptor (0, 1, or 2) was closed and has been
+    /// reopened to `/dev/null`.
+    #[inline]
+    pub fn reopened(&self, fd: libc::c_int) -> bool {
+        match fd {
+            0..=2 => self.reopened[fd as usize],
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if no file descriptors were reopened.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.reopened == [false; 3]
+    }
+}
+
+/// Make sure that file descriptors 0, 1, and 2 (stdin, stdout, and stderr) are open.
+///
+/// If `apply` says this should take effect, this checks each of the three file descriptors with
+/// `fcntl(F_GETFD)`, and opens `/dev/null` in its place if it is closed (read-only for stdin, and
+/// write-only for stdout and stderr). Otherwise, it does nothing.
+///
+/// Without this, an attacker could launch a set-UID program with (for example) stderr closed; the
+/// next file the program opened would then be assigned file descriptor 2, and anything the
+/// program wrote to stderr would be written to that file. glibc performs this check itself for
+/// programs that require "secure execution", but other C libraries (such as musl) may not.
+///
+/// This should be called at the start of `main()`, before any other files are opened.
+pub fn ensure_std_fds(apply: Apply) -> Result<StdFdsReport, Errno> {
+    let mut report = StdFdsReport::default();
+
+    if !apply.should_apply() {
+        return Ok(report);
+    }
+
+    for fd in 0..3 {
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1 || Errno::last().raw() != libc::EBADF {
+            continue;
+        }
+
+        let flags = if fd == 0 {
+            libc::O_RDONLY
+        } else {
+            libc::O_WRONLY
+        };
+
+        reopen_devnull(fd, flags)?;
+        report.reopened[fd as usize] = true;
+    }
+
+    Ok(report)
+}
+
+// `st_mode` and `mode_t` are not the same type on every platform
+#[allow(clippy::unnecessary_cast)]
+fn reopen_devnull(fd: libc::c_int, flags: libc::c_int) -> Result<(), Errno> {
+    let newfd = unsafe {
+        libc::open(
+            b"/dev/null\0".as_ptr() as *const libc::c_char,
+            flags | libc::O_NOFOLLOW,
+        )
+    };
+    if newfd < 0 {
+        return Err(Errno::last());
+    }
+
+    // Make sure it's actually a character device
+    let mut st = unsafe { core::mem::zeroed::<libc::stat>() };
+    if unsafe { libc::fstat(newfd, &mut st) } != 0
+        || st.st_mode as u32 & libc::S_IFMT as u32 != libc::S_IFCHR as u32
+    {
+        unsafe {
+            libc::close(newfd);
+        }
+        return Err(Errno::from_raw(libc::ENODEV));
+    }
+
+    // Since we handle the file descriptors in order, the new file descriptor should be the one
+    // that was closed. But check, just in case.
+    if newfd != fd {
+        let res = unsafe { libc::dup2(newfd, fd) };
+        let err = Errno::last();
+        unsafe {
+            libc::close(newfd);
+        }
+        if res < 0 {
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// The action taken by [`sanitize_inherited_fds()`] on each inherited file descriptor.
+///
+/// [`sanitize_inherited_fds()`]: ./fn.sanitize_inherited_fds.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FdAction {
+    /// Close the file descriptor.
+    Close,
+    /// Set the close-on-exec flag on the file descriptor, so it will not be passed on to any
+    /// programs this one executes.
+    CloseOnExec,
+}
+
+/// Close (or mark close-on-exec) every file descriptor above 2 that is not in `keep`.
+///
+/// If `apply` says this should take effect, this first determines which file descriptors are
+/// open (by listing `/proc/self/fd` or `/dev/fd` where possible, or by checking every file
+/// descriptor up to `RLIMIT_NOFILE` otherwise). It then applies `action` to each of them, using
+/// `close_range()` where available (which also catches file descriptors that could not be found
+/// while listing). Otherwise, it does nothing and returns an empty list.
+///
+/// Negative values in `keep` are ignored.
+///
+/// Returns a sorted list of the file descriptors that were found open and acted on.
+///
+/// This should be called at the start of `main()`, before any other threads are spawned (since
+/// they might be opening file descriptors of their own).
+#[cfg(feature = "std")]
+pub fn sanitize_inherited_fds(
+    keep: &[libc::c_int],
+    action: FdAction,
+    apply: Apply,
+) -> Result<Vec<libc::c_int>, Errno> {
+    if !apply.should_apply() {
+        return Ok(Vec::new());
+    }
+
+    // Negative entries (such as -1 sentinels) would wrap around in close_range()
+    let mut keep: Vec<libc::c_int> = keep.iter().copied().filter(|&fd| fd >= 0).collect();
+    keep.extend(0..3);
+    keep.sort_unstable();
+    keep.dedup();
+
+    let mut fds = list_open_fds();
+    fds.retain(|fd| keep.binary_search(fd).is_err());
+
+    if !close_range_except(&keep, action) {
+        for &fd in fds.iter() {
+            match action {
+                FdAction::Close => unsafe {
+                    libc::close(fd);
+                },
+
+                FdAction::CloseOnExec => {
+                    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+                    if flags < 0
+                        || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } < 0
+                    {
+                        return Err(Errno::last());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(fds)
+}
+
+#[cfg(feature = "std")]
+#[inline]
+fn is_open(fd: libc::c_int) -> bool {
+    unsafe { libc::fcntl(fd, libc::F_GETFD) >= 0 }
+}
+
+/// List the open file descriptors, in sorted order.
+#[cfg(feature = "std")]
+fn list_open_fds() -> Vec<libc::c_int> {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "solaris",
+        target_os = "illumos",
+    ))]
+    let fd_dir = Some("/proc/self/fd");
+    // On FreeBSD, /dev/fd only lists file descriptors 0-2 unless fdescfs is mounted
+    #[cfg(target_os = "macos")]
+    let fd_dir = Some("/dev/fd");
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "solaris",
+        target_os = "illumos",
+        target_os = "macos",
+    )))]
+    let fd_dir: Option<&str> = None;
+
+    if let Some(entries) = fd_dir.and_then(|dir| std::fs::read_dir(dir).ok()) {
+        let mut fds: Vec<libc::c_int> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+
+        // The list includes the file descriptor used to read the directory, which is now closed
+        fds.retain(|&fd| is_open(fd));
+        fds.sort_unstable();
+        return fds;
+    }
+
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let maxfd = if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } == 0
+        && rlim.rlim_cur != libc::RLIM_INFINITY
+    {
+        rlim.rlim_cur.min(1 << 20) as libc::c_int
+    } else {
+        1 << 20
+    };
+
+    (0..maxfd).filter(|&fd| is_open(fd)).collect()
+}
+
+/// Apply `action` to every file descriptor not in `keep` (which must be sorted) with
+/// `close_range()`. Returns `false` if `close_range()` is not available (or does not support the
+/// requested action).
+#[cfg(feature = "std")]
+fn close_range_except(keep: &[libc::c_int], action: FdAction) -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let close_range = |first: libc::c_uint, last: libc::c_uint, flags: libc::c_uint| unsafe {
+                libc::syscall(libc::SYS_close_range, first, last, flags) as libc::c_int
+            };
+            // libc only defines this for Linux (not Android)
+            const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+        } else if #[cfg(target_os = "freebsd")] {
+            let close_range = |first: libc::c_uint, last: libc::c_uint, flags: libc::c_uint| unsafe {
+                libc::close_range(first, last, flags as libc::c_int)
+            };
+            const CLOSE_RANGE_CLOEXEC: libc::c_uint = libc::CLOSE_RANGE_CLOEXEC as libc::c_uint;
+        } else {
+            let _ = (keep, action);
+            return false;
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    {
+        let flags = match action {
+            FdAction::Close => 0,
+            FdAction::CloseOnExec => CLOSE_RANGE_CLOEXEC,
+        };
+
+        let mut first = 0;
+        for &fd in keep.iter().chain(core::iter::once(&libc::c_int::MAX)) {
+            let fd = fd as libc::c_uint;
+            if fd > first && close_range(first, fd - 1, flags) != 0 {
+                // Either close_range() isn't supported, or it doesn't support this flag. In
+                // either case, it will have failed on the first call, so nothing was done.
+                return false;
+            }
+            first = fd + 1;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_ensure_std_fds() {
+        assert!(ensure_std_fds(Apply::IfSecure).unwrap().is_empty());
+
+        assert!(run_in_child(|| {
+            unsafe {
+                libc::close(0);
+                libc::close(2);
+            }
+
+            let report = ensure_std_fds(Apply::Always).unwrap();
+
+            report.reopened(0)
+                && !report.reopened(1)
+                && report.reopened(2)
+                && unsafe { libc::fcntl(0, libc::F_GETFL) } & libc::O_ACCMODE == libc::O_RDONLY
+                && unsafe { libc::fcntl(2, libc::F_GETFL) } & libc::O_ACCMODE == libc::O_WRONLY
+        }));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_sanitize_inherited_fds() {
+        use std::os::unix::io::{AsRawFd, IntoRawFd};
+
+        assert_eq!(
+            sanitize_inherited_fds(&[], FdAction::Close, Apply::IfSecure),
+            Ok(Vec::new())
+        );
+
+        assert!(run_in_child(|| {
+            let keep = std::fs::File::open("/dev/null").unwrap();
+            let close = std::fs::File::open("/dev/null").unwrap();
+
+            let fds =
+                sanitize_inherited_fds(&[keep.as_raw_fd()], FdAction::CloseOnExec, Apply::Always)
+                    .unwrap();
+
+            let cloexec = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0;
+
+            // std opens files with O_CLOEXEC, so clear it to check that it gets set again
+            unsafe {
+                libc::fcntl(keep.as_raw_fd(), libc::F_SETFD, 0);
+                libc::fcntl(close.as_raw_fd(), libc::F_SETFD, 0);
+            }
+            sanitize_inherited_fds(&[keep.as_raw_fd()], FdAction::CloseOnExec, Apply::Always)
+                .unwrap();
+
+            fds.contains(&close.as_raw_fd())
+                && !fds.contains(&keep.as_raw_fd())
+                && !fds.iter().any(|&fd| fd <= 2)
+                && cloexec(close.as_raw_fd())
+                && !cloexec(keep.as_raw_fd())
+        }));
+
+        assert!(run_in_child(|| {
+            let keep = std::fs::File::open("/dev/null").unwrap().into_raw_fd();
+            let close = std::fs::File::open("/dev/null").unwrap().into_raw_fd();
+
+            let fds = sanitize_inherited_fds(&[keep], FdAction::Close, Apply::Always).unwrap();
+
+            fds.contains(&close) && is_open(keep) && !is_open(close) && is_open(2)
+        }));
+
+        assert!(run_in_child(|| {
+            let close = std::fs::File::open("/dev/null").unwrap().into_raw_fd();
+
+            let fds = sanitize_inherited_fds(&[-1], FdAction::Close, Apply::Always).unwrap();
+
+            fds.contains(&close) && !is_open(close) && is_open(0) && is_open(1) && is_open(2)
+        }));
+    }
+}
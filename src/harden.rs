@@ -0,0 +1,333 @@
+use core::fmt;
+
+use crate::env::sanitize_environment_unconditional;
+use crate::{
+    ensure_std_fds, reset_signal_state, sanitize_inherited_fds, sanitize_rlimits, sanitize_umask,
+    Apply, EnvReport, Errno, FdAction, RlimitPolicy, RlimitReport, SignalReport, StdFdsReport,
+    DEFAULT_UMASK,
+};
+
+/// Controls which steps [`harden()`] performs.
+///
+/// Every step is enabled by default.
+///
+/// [`harden()`]: ./fn.harden.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HardenConfig {
+    /// When the steps should be performed. (Default: [`Apply::IfSecure`].)
+    ///
+    /// [`Apply::IfSecure`]: ./enum.Apply.html#variant.IfSecure
+    pub apply: Apply,
+    /// Reopen closed standard file descriptors with [`ensure_std_fds()`].
+    ///
+    /// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+    pub std_fds: bool,
+    /// Remove untrusted environment variables, as [`sanitize_environment()`] does.
+    ///
+    /// [`sanitize_environment()`]: ./fn.sanitize_environment.html
+    pub environment: bool,
+    /// Reset the signal mask and ignored signals with [`reset_signal_state()`].
+    ///
+    /// [`reset_signal_state()`]: ./fn.reset_signal_state.html
+    pub signals: bool,
+    /// The ignored signals to leave alone when resetting signals. (Default: `SIGPIPE`, which the
+    /// Rust standard library ignores at startup.)
+    pub keep_ignored_signals: Vec<libc::c_int>,
+    /// Reset resource limits with [`sanitize_rlimits()`] and the given policy. (The policy's
+    /// `apply` field is ignored.)
+    ///
+    /// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+    pub rlimits: Option<RlimitPolicy>,
+    /// Close (or mark close-on-exec) inherited file descriptors with
+    /// [`sanitize_inherited_fds()`].
+    ///
+    /// [`sanitize_inherited_fds()`]: ./fn.sanitize_inherited_fds.html
+    pub inherited_fds: bool,
+    /// The file descriptors (above 2) to leave open.
+    pub keep_fds: Vec<libc::c_int>,
+    /// What to do with inherited file descriptors. (Default: [`FdAction::Close`].)
+    ///
+    /// [`FdAction::Close`]: ./enum.FdAction.html#variant.Close
+    pub fd_action: FdAction,
+    /// Set the umask to the given value with [`sanitize_umask()`]. (Default:
+    /// `Some(DEFAULT_UMASK)`.)
+    ///
+    /// [`sanitize_umask()`]: ./fn.sanitize_umask.html
+    pub umask: Option<libc::mode_t>,
+}
+
+impl Default for HardenConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            apply: Apply::IfSecure,
+            std_fds: true,
+            environment: true,
+            signals: true,
+            keep_ignored_signals: vec![libc::SIGPIPE],
+            rlimits: Some(RlimitPolicy::default()),
+            inherited_fds: true,
+            keep_fds: Vec::new(),
+            fd_action: FdAction::Close,
+            umask: Some(DEFAULT_UMASK),
+        }
+    }
+}
+
+/// A report of the actions taken by [`harden()`].
+///
+/// Each method returns `None` if the corresponding step was disabled (or if no steps were
+/// performed because the program does not require "secure execution").
+///
+/// [`harden()`]: ./fn.harden.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HardenReport {
+    applied: bool,
+    std_fds: Option<StdFdsReport>,
+    environment: Option<EnvReport>,
+    signals: Option<SignalReport>,
+    rlimits: Option<RlimitReport>,
+    inherited_fds: Option<Vec<libc::c_int>>,
+    umask: Option<libc::mode_t>,
+}
+
+impl HardenReport {
+    /// Returns `true` if the steps were performed (that is, if the config's `apply` field said
+    /// they should take effect).
+    #[inline]
+    pub fn applied(&self) -> bool {
+        self.applied
+    }
+
+    /// Get the report from [`ensure_std_fds()`].
+    ///
+    /// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+    #[inline]
+    pub fn std_fds(&self) -> Option<StdFdsReport> {
+        self.std_fds
+    }
+
+    /// Get the report of the environment variables that were removed.
+    #[inline]
+    pub fn environment(&self) -> Option<&EnvReport> {
+        self.environment.as_ref()
+    }
+
+    /// Get the report from [`reset_signal_state()`].
+    ///
+    /// [`reset_signal_state()`]: ./fn.reset_signal_state.html
+    #[inline]
+    pub fn signals(&self) -> Option<SignalReport> {
+        self.signals
+    }
+
+    /// Get the report from [`sanitize_rlimits()`].
+    ///
+    /// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+    #[inline]
+    pub fn rlimits(&self) -> Option<RlimitReport> {
+        self.rlimits
+    }
+
+    /// Get the list of inherited file descriptors that were closed (or marked close-on-exec).
+    #[inline]
+    pub fn inherited_fds(&self) -> Option<&[libc::c_int]> {
+        self.inherited_fds.as_deref()
+    }
+
+    /// Get the umask that was in effect before it was changed.
+    #[inline]
+    pub fn umask(&self) -> Option<libc::mode_t> {
+        self.umask
+    }
+}
+
+/// An error returned by [`harden()`], indicating which step failed.
+///
+/// [`harden()`]: ./fn.harden.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HardenError {
+    /// [`ensure_std_fds()`] failed.
+    ///
+    /// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+    StdFds(Errno),
+    /// [`reset_signal_state()`] failed.
+    ///
+    /// [`reset_signal_state()`]: ./fn.reset_signal_state.html
+    Signals(Errno),
+    /// [`sanitize_rlimits()`] failed.
+    ///
+    /// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+    Rlimits(Errno),
+    /// [`sanitize_inherited_fds()`] failed.
+    ///
+    /// [`sanitize_inherited_fds()`]: ./fn.sanitize_inherited_fds.html
+    InheritedFds(Errno),
+}
+
+impl HardenError {
+    /// Get the underlying OS error.
+    #[inline]
+    pub fn errno(&self) -> Errno {
+        match *self {
+            Self::StdFds(errno)
+            | Self::Signals(errno)
+            | Self::Rlimits(errno)
+            | Self::InheritedFds(errno) => errno,
+        }
+    }
+}
+
+impl fmt::Display for HardenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let step = match *self {
+            Self::StdFds(_) => "reopen standard file descriptors",
+            Self::Signals(_) => "reset signal state",
+            Self::Rlimits(_) => "reset resource limits",
+            Self::InheritedFds(_) => "sanitize inherited file descriptors",
+        };
+        write!(f, "failed to {}: {}", step, self.errno())
+    }
+}
+
+impl std::error::Error for HardenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::StdFds(errno)
+            | Self::Signals(errno)
+            | Self::Rlimits(errno)
+            | Self::InheritedFds(errno) => Some(errno),
+        }
+    }
+}
+
+/// Apply every mitigation in this crate that is appropriate at program startup.
+///
+/// This first primes the caches used by [`is_secure()`], [`secure_reason()`], and
+/// [`initial_credentials()`]. Then, if `config.apply` says the steps should take effect, it
+/// performs each enabled step in the following order:
+///
+/// 1. Reopen closed standard file descriptors ([`ensure_std_fds()`]).
+/// 2. Remove untrusted environment variables ([`sanitize_environment()`]).
+/// 3. Reset the signal mask and ignored signals ([`reset_signal_state()`]).
+/// 4. Reset resource limits ([`sanitize_rlimits()`]). This happens before the next step so that
+///    a lowered `RLIMIT_NOFILE` cannot hide inherited file descriptors.
+/// 5. Close inherited file descriptors ([`sanitize_inherited_fds()`]).
+/// 6. Set the umask ([`sanitize_umask()`]).
+///
+/// If a step fails, the remaining steps are not performed.
+///
+/// This should be called first thing in `main()`, before any other threads are spawned:
+///
+/// ```no_run
+/// secure_exec::harden(Default::default()).unwrap();
+/// ```
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+/// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+/// [`sanitize_environment()`]: ./fn.sanitize_environment.html
+/// [`reset_signal_state()`]: ./fn.reset_signal_state.html
+/// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+/// [`sanitize_inherited_fds()`]: ./fn.sanitize_inherited_fds.html
+/// [`sanitize_umask()`]: ./fn.sanitize_umask.html
+pub fn harden(config: HardenConfig) -> Result<HardenReport, HardenError> {
+    crate::is_secure();
+    crate::secure_reason();
+    crate::initial_credentials();
+
+    let mut report = HardenReport::default();
+
+    if !config.apply.should_apply() {
+        return Ok(report);
+    }
+    report.applied = true;
+
+    if config.std_fds {
+        report.std_fds = Some(ensure_std_fds(Apply::Always).map_err(HardenError::StdFds)?);
+    }
+
+    if config.environment {
+        report.environment = Some(sanitize_environment_unconditional());
+    }
+
+    if config.signals {
+        report.signals = Some(
+            reset_signal_state(&config.keep_ignored_signals, Apply::Always)
+                .map_err(HardenError::Signals)?,
+        );
+    }
+
+    if let Some(policy) = config.rlimits {
+        report.rlimits = Some(
+            sanitize_rlimits(&RlimitPolicy {
+                apply: Apply::Always,
+                ..policy
+            })
+            .map_err(HardenError::Rlimits)?,
+        );
+    }
+
+    if config.inherited_fds {
+        report.inherited_fds = Some(
+            sanitize_inherited_fds(&config.keep_fds, config.fd_action, Apply::Always)
+                .map_err(HardenError::InheritedFds)?,
+        );
+    }
+
+    if let Some(mask) = config.umask {
+        report.umask = Some(sanitize_umask(mask, Apply::Always));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::io::IntoRawFd;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_harden() {
+        let report = harden(HardenConfig::default()).unwrap();
+        assert!(!report.applied());
+        assert_eq!(report, HardenReport::default());
+
+        assert!(run_in_child(|| {
+            std::env::set_var("LD_PRELOAD", "/nonexistent.so");
+            let fd = std::fs::File::open("/dev/null").unwrap().into_raw_fd();
+            unsafe {
+                libc::umask(0o077);
+            }
+
+            let report = harden(HardenConfig {
+                apply: Apply::Always,
+                ..Default::default()
+            })
+            .unwrap();
+
+            let umask = unsafe { libc::umask(DEFAULT_UMASK) };
+
+            report.applied()
+                && report.std_fds().is_some()
+                && report
+                    .environment()
+                    .unwrap()
+                    .removed()
+                    .iter()
+                    .any(|name| name == "LD_PRELOAD")
+                && std::env::var_os("LD_PRELOAD").is_none()
+                && report.signals().is_some()
+                && report.rlimits().unwrap().core().is_some()
+                && report.inherited_fds().unwrap().contains(&fd)
+                && unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1
+                && report.umask() == Some(0o077)
+                && umask == DEFAULT_UMASK
+                && crate::inherited_umask() == Some(0o077)
+        }));
+    }
+}
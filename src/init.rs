@@ -0,0 +1,25 @@
+//! Support for capturing the secure-execution state from a constructor that runs before `main()`
+//! (enabled with the `constructor` feature).
+
+extern "C" fn init() {
+    crate::is_secure();
+    crate::secure_reason();
+    crate::initial_credentials();
+}
+
+#[used]
+#[cfg_attr(
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly",
+        target_os = "solaris",
+        target_os = "illumos",
+    ),
+    link_section = ".init_array"
+)]
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__mod_init_func")]
+static INIT: extern "C" fn() = init;
@@ -0,0 +1,158 @@
+use crate::rlimits::{disable_core_dumps, Resource, Rlimit};
+use crate::{Apply, Errno};
+
+/// The current state of the protections applied by [`harden_against_inspection()`], as returned
+/// by [`inspection_state()`].
+///
+/// [`harden_against_inspection()`]: ./fn.harden_against_inspection.html
+/// [`inspection_state()`]: ./fn.inspection_state.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct InspectionState {
+    traceable: Option<bool>,
+    core_dumps: bool,
+}
+
+impl InspectionState {
+    /// Returns whether other processes running as the same user can attach to this process (with
+    /// `ptrace()` or similar), or `None` if this cannot be determined on the current platform.
+    ///
+    /// On Linux, this reflects the "dumpable" flag (`PR_GET_DUMPABLE`): only a value of 1 means
+    /// the process is traceable by its owner, while 0 and 2 (`SUID_DUMP_ROOT`, which set-ID
+    /// programs get when `fs.suid_dumpable` is 2) restrict attaching to processes with
+    /// `CAP_SYS_PTRACE`. On FreeBSD, it reflects `PROC_TRACE_STATUS`.
+    #[inline]
+    pub fn traceable(&self) -> Option<bool> {
+        self.traceable
+    }
+
+    /// Returns whether the soft limit for `RLIMIT_CORE` allows core dumps to be written.
+    #[inline]
+    pub fn core_dumps(&self) -> bool {
+        self.core_dumps
+    }
+}
+
+/// Get the current state of the protections applied by [`harden_against_inspection()`].
+///
+/// [`harden_against_inspection()`]: ./fn.harden_against_inspection.html
+pub fn inspection_state() -> InspectionState {
+    let core_dumps = Rlimit::get(libc::RLIMIT_CORE as Resource).map_or(true, |rlim| rlim.soft != 0);
+
+    InspectionState {
+        traceable: is_traceable(),
+        core_dumps,
+    }
+}
+
+#[allow(clippy::needless_return)]
+fn is_traceable() -> Option<bool> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            return match unsafe { libc::prctl(libc::PR_GET_DUMPABLE) } {
+                1 => Some(true),
+                0 | 2 => Some(false),
+                _ => None,
+            };
+        } else if #[cfg(target_os = "freebsd")] {
+            let mut status: libc::c_int = 0;
+            if unsafe {
+                libc::procctl(
+                    libc::P_PID,
+                    0,
+                    libc::PROC_TRACE_STATUS,
+                    &mut status as *mut _ as *mut libc::c_void,
+                )
+            } != 0
+            {
+                return None;
+            }
+            // -1 means tracing is disabled; otherwise, it's the PID of the tracer (or 0)
+            return Some(status != -1);
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Prevent other processes from inspecting this process's memory, by attaching to it or by
+/// reading a core dump.
+///
+/// Even after a set-UID program has dropped privileges, secrets that it read while privileged may
+/// still be present in its memory, where they could be extracted by a debugger running as the
+/// same user or from a core dump.
+///
+/// If `apply` says this should take effect, this:
+///
+/// - On Linux, clears the "dumpable" flag with `prctl(PR_SET_DUMPABLE, 0)`, which prevents
+///   unprivileged processes from attaching to this one with `ptrace()` or reading its
+///   `/proc/<pid>/mem`, and prevents core dumps.
+/// - On FreeBSD, disables tracing with `procctl(PROC_TRACE_CTL, PROC_TRACE_CTL_DISABLE)`.
+/// - On macOS, calls `ptrace(PT_DENY_ATTACH)`. Note that if the process is already being traced,
+///   this causes it to exit.
+/// - On all platforms, sets both limits for `RLIMIT_CORE` to 0.
+///
+/// Otherwise, it does nothing.
+///
+/// Note that on Linux, the "dumpable" flag is reset when the process executes another program.
+pub fn harden_against_inspection(apply: Apply) -> Result<(), Errno> {
+    if !apply.should_apply() {
+        return Ok(());
+    }
+
+    deny_attach()?;
+    disable_core_dumps()?;
+
+    Ok(())
+}
+
+#[allow(clippy::needless_return)]
+fn deny_attach() -> Result<(), Errno> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+                return Err(Errno::last());
+            }
+            return Ok(());
+        } else if #[cfg(target_os = "freebsd")] {
+            let mut ctl = libc::PROC_TRACE_CTL_DISABLE;
+            if unsafe {
+                libc::procctl(
+                    libc::P_PID,
+                    0,
+                    libc::PROC_TRACE_CTL,
+                    &mut ctl as *mut _ as *mut libc::c_void,
+                )
+            } != 0
+            {
+                return Err(Errno::last());
+            }
+            return Ok(());
+        } else if #[cfg(target_os = "macos")] {
+            if unsafe { libc::ptrace(libc::PT_DENY_ATTACH, 0, core::ptr::null_mut(), 0) } != 0 {
+                return Err(Errno::last());
+            }
+            return Ok(());
+        } else {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_harden_against_inspection() {
+        assert_eq!(harden_against_inspection(Apply::IfSecure), Ok(()));
+
+        assert!(run_in_child(|| {
+            harden_against_inspection(Apply::Always).unwrap();
+
+            let state = inspection_state();
+            state.traceable() != Some(true) && !state.core_dumps()
+        }));
+    }
+}
@@ -13,6 +13,67 @@
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod auxv;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod capabilities;
+mod creds;
+#[cfg(feature = "std")]
+mod env;
+mod errno;
+mod fds;
+#[cfg(feature = "std")]
+mod harden;
+#[cfg(feature = "constructor")]
+mod init;
+mod inspection;
+#[cfg(feature = "std")]
+mod open;
+mod privs;
+mod reason;
+mod rlimits;
+mod signals;
+mod trace;
+#[cfg(feature = "std")]
+mod trust;
+mod umask;
+mod util;
+#[cfg(feature = "std")]
+mod which;
+
+pub use creds::{initial_credentials, Credentials};
+#[cfg(feature = "std")]
+pub use env::{
+    sanitize_environment, EnvReport, SecureEnv, VarsOs, GLIBC_UNSECURE_ENVVARS,
+    RUST_UNSECURE_ENVVARS, UNSECURE_ENVVAR_PREFIXES,
+};
+pub use errno::Errno;
+#[cfg(feature = "std")]
+pub use fds::sanitize_inherited_fds;
+pub use fds::{ensure_std_fds, FdAction, StdFdsReport};
+#[cfg(feature = "std")]
+pub use harden::{harden, HardenConfig, HardenError, HardenReport};
+pub use inspection::{harden_against_inspection, inspection_state, InspectionState};
+#[cfg(feature = "std")]
+pub use open::{secure_open, SecureOpenOptions};
+#[cfg(feature = "std")]
+pub use privs::open_as_real_user;
+pub use privs::{drop_privileges_permanently, drop_privileges_to, DropError, TemporaryDrop};
+pub use reason::{
+    no_new_privs, secure_reason, secure_reason_uncached, ungranted_privileges, SecureReason,
+};
+pub use rlimits::{sanitize_rlimits, Rlimit, RlimitPolicy, RlimitReport, DEFAULT_MIN_STACK};
+pub use signals::{reset_signal_state, SignalReport};
+pub use trace::{is_traced, tracer_pid};
+#[cfg(feature = "std")]
+pub use trust::{verify_trusted_path, TrustError, TrustErrorKind, TrustPolicy};
+pub use umask::{inherited_umask, sanitize_umask, DEFAULT_UMASK};
+#[cfg(feature = "std")]
+pub use which::{secure_default_path, secure_which, SECURE_DEFAULT_PATH};
+
 /// Identical to [`is_secure()`], but with no caching (i.e. probes the OS-specific feature directly).
 ///
 /// See the warnings in [`is_secure()`]'s documentation regarding the result changing.
@@ -27,6 +88,16 @@ pub fn is_secure_uncached() -> bool {
             all(target_os = "android", target_pointer_width = "64"),
         ))] {
             return unsafe { libc::getauxval(libc::AT_SECURE) } != 0;
+        } else if #[cfg(target_os = "android")] {
+            // Older 32-bit Android releases don't have getauxval(), so read /proc/self/auxv
+            // directly, falling back on comparing UIDs/GIDs if that fails.
+            if let Some(secure) = auxv::getauxval(libc::AT_SECURE) {
+                return secure != 0;
+            }
+
+            return unsafe {
+                libc::geteuid() != libc::getuid() || libc::getegid() != libc::getgid()
+            };
         } else if #[cfg(any(
             target_os = "freebsd",
             target_os = "openbsd",
@@ -54,7 +125,8 @@ pub fn is_secure_uncached() -> bool {
 /// This usually means that the executed binary is set-UID, set-GID, or (on Linux) has file
 /// capabilities.
 ///
-/// On Linux, this calls `getauxval(AT_SECURE)`; on macOS, the BSDs, and Solaris/Illumos, it calls
+/// On Linux, this calls `getauxval(AT_SECURE)` (on 32-bit Android, it reads `AT_SECURE` from
+/// `/proc/self/auxv`); on macOS, the BSDs, and Solaris/Illumos, it calls
 /// `issetugid()`; and on other platforms it checks if the effective UID/GID is different from the
 /// real UID/GID. The result is cached after the first call.
 ///
@@ -70,6 +142,11 @@ pub fn is_secure_uncached() -> bool {
 /// to change. In binary crates, it's recommended to call this function at the start of `main()`,
 /// even if you don't need the value right away.
 ///
+/// Alternatively, enable this crate's `constructor` feature. On ELF platforms and macOS, this
+/// records the result of this function (along with [`secure_reason()`] and
+/// [`initial_credentials()`]) from a constructor that runs before `main()`, so the cached value
+/// always reflects the state at `exec()` time regardless of when it is first called.
+///
 /// ## Concurrency
 ///
 /// **TL;DR**: If you're going to switch UIDs/GIDs, either avoid calling this function from multiple
@@ -90,6 +167,8 @@ pub fn is_secure_uncached() -> bool {
 /// launching any other threads.
 ///
 /// [`is_secure_uncached()`]: ./fn.is_secure_uncached.html
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
 pub fn is_secure() -> bool {
     use core::sync::atomic::{AtomicU8, Ordering};
 
@@ -108,6 +187,40 @@ pub fn is_secure() -> bool {
     }
 }
 
+/// Controls when one of this crate's sanitizing operations (for example, [`ensure_std_fds()`])
+/// takes effect.
+///
+/// [`ensure_std_fds()`]: ./fn.ensure_std_fds.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Apply {
+    /// Only if [`is_secure()`] returns `true` (the default).
+    ///
+    /// [`is_secure()`]: ./fn.is_secure.html
+    IfSecure,
+    /// Always, regardless of what [`is_secure()`] returns.
+    ///
+    /// [`is_secure()`]: ./fn.is_secure.html
+    Always,
+}
+
+impl Apply {
+    /// Returns `true` if the operation should take effect in the current process.
+    #[inline]
+    pub fn should_apply(self) -> bool {
+        match self {
+            Self::IfSecure => is_secure(),
+            Self::Always => true,
+        }
+    }
+}
+
+impl Default for Apply {
+    #[inline]
+    fn default() -> Self {
+        Self::IfSecure
+    }
+}
+
 /// Get the specified environmental variable from the current process's environment (unless the
 /// program requires "secure execution").
 ///
@@ -142,6 +255,305 @@ pub fn secure_getenv_os<K: AsRef<std::ffi::OsStr>>(key: K) -> Option<std::ffi::O
     }
 }
 
+/// An error returned by [`secure_getenv_detailed()`] and [`secure_getenv_os_detailed()`].
+///
+/// Unlike `std::env::VarError`, this distinguishes between a variable that is missing and a
+/// variable that was deliberately suppressed because the program requires "secure execution".
+///
+/// [`secure_getenv_detailed()`]: ./fn.secure_getenv_detailed.html
+/// [`secure_getenv_os_detailed()`]: ./fn.secure_getenv_os_detailed.html
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SecureGetenvError {
+    /// The variable was ignored because the program requires "secure execution" (for the given
+    /// reason).
+    Suppressed(SecureReason),
+    /// The variable is not present in the environment.
+    NotPresent,
+    /// The variable's value is not valid Unicode.
+    NotUnicode(std::ffi::OsString),
+}
+
+#[cfg(feature = "std")]
+impl From<std::env::VarError> for SecureGetenvError {
+    #[inline]
+    fn from(err: std::env::VarError) -> Self {
+        match err {
+            std::env::VarError::NotPresent => Self::NotPresent,
+            std::env::VarError::NotUnicode(value) => Self::NotUnicode(value),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<SecureGetenvError> for std::env::VarError {
+    /// Convert to a `VarError`. [`SecureGetenvError::Suppressed`] becomes
+    /// `VarError::NotPresent`.
+    ///
+    /// [`SecureGetenvError::Suppressed`]: ./enum.SecureGetenvError.html#variant.Suppressed
+    #[inline]
+    fn from(err: SecureGetenvError) -> Self {
+        match err {
+            SecureGetenvError::Suppressed(_) | SecureGetenvError::NotPresent => Self::NotPresent,
+            SecureGetenvError::NotUnicode(value) => Self::NotUnicode(value),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for SecureGetenvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Suppressed(reason) => write!(
+                f,
+                "environment variable ignored because of secure execution ({:?})",
+                reason
+            ),
+            Self::NotPresent => f.write_str("environment variable not found"),
+            Self::NotUnicode(value) => {
+                write!(f, "environment variable was not valid unicode: {:?}", value)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SecureGetenvError {}
+
+/// Get the specified environmental variable from the current process's environment (unless the
+/// program requires "secure execution").
+///
+/// This is like [`secure_getenv()`], but if [`is_secure()`] returns true it returns
+/// [`SecureGetenvError::Suppressed`] (with the reason reported by [`secure_reason()`]) instead of
+/// a "not found" error.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`secure_getenv()`]: ./fn.secure_getenv.html
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`SecureGetenvError::Suppressed`]: ./enum.SecureGetenvError.html#variant.Suppressed
+#[cfg(feature = "std")]
+pub fn secure_getenv_detailed<K: AsRef<std::ffi::OsStr>>(
+    key: K,
+) -> Result<String, SecureGetenvError> {
+    secure_getenv_os_detailed(key)?
+        .into_string()
+        .map_err(SecureGetenvError::NotUnicode)
+}
+
+/// Get the specified environmental variable from the current process's environment (unless the
+/// program requires "secure execution").
+///
+/// This is like [`secure_getenv_os()`], but it returns an error that distinguishes between the
+/// variable being missing and being suppressed because [`is_secure()`] returned true.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`secure_getenv_os()`]: ./fn.secure_getenv_os.html
+#[cfg(feature = "std")]
+pub fn secure_getenv_os_detailed<K: AsRef<std::ffi::OsStr>>(
+    key: K,
+) -> Result<std::ffi::OsString, SecureGetenvError> {
+    if is_secure() {
+        Err(SecureGetenvError::Suppressed(secure_reason()))
+    } else {
+        std::env::var_os(key).ok_or(SecureGetenvError::NotPresent)
+    }
+}
+
+/// An error returned by the typed `secure_getenv_*()` functions (for example,
+/// [`secure_getenv_parse()`]).
+///
+/// `E` is the type of the error returned when the variable's value fails validation.
+///
+/// [`secure_getenv_parse()`]: ./fn.secure_getenv_parse.html
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SecureVarError<E> {
+    /// The variable was ignored because the program requires "secure execution".
+    Suppressed(SecureReason),
+    /// The variable is not present in the environment.
+    NotPresent,
+    /// The variable's value is not valid Unicode.
+    NotUnicode(std::ffi::OsString),
+    /// The variable's value could not be parsed or failed validation.
+    Invalid(E),
+}
+
+#[cfg(feature = "std")]
+impl<E> From<SecureGetenvError> for SecureVarError<E> {
+    #[inline]
+    fn from(err: SecureGetenvError) -> Self {
+        match err {
+            SecureGetenvError::Suppressed(reason) => Self::Suppressed(reason),
+            SecureGetenvError::NotPresent => Self::NotPresent,
+            SecureGetenvError::NotUnicode(value) => Self::NotUnicode(value),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::fmt::Display> std::fmt::Display for SecureVarError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Suppressed(reason) => write!(
+                f,
+                "environment variable ignored because of secure execution ({:?})",
+                reason
+            ),
+            Self::NotPresent => f.write_str("environment variable not found"),
+            Self::NotUnicode(value) => {
+                write!(f, "environment variable was not valid unicode: {:?}", value)
+            }
+            Self::Invalid(err) => write!(f, "environment variable was not valid: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for SecureVarError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The reason a path was rejected by [`secure_getenv_path()`].
+///
+/// [`secure_getenv_path()`]: ./fn.secure_getenv_path.html
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InvalidPath {
+    /// The path is not absolute.
+    Relative,
+    /// The path contains a NUL byte.
+    ContainsNul,
+    /// The path contains a `..` component.
+    ParentDir,
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for InvalidPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Relative => "path is not absolute",
+            Self::ContainsNul => "path contains a NUL byte",
+            Self::ParentDir => "path contains a '..' component",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidPath {}
+
+/// The error used by [`secure_getenv_bool()`] when a value is not a recognized boolean.
+///
+/// [`secure_getenv_bool()`]: ./fn.secure_getenv_bool.html
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct InvalidBool;
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for InvalidBool {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("value is not a recognized boolean")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidBool {}
+
+/// Get the specified environmental variable and parse it with `FromStr` (unless the program
+/// requires "secure execution").
+///
+/// [`SecureVarError`] distinguishes between the variable being suppressed because
+/// [`is_secure()`] returned true, being missing, not being valid Unicode, and failing to parse.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`SecureVarError`]: ./enum.SecureVarError.html
+#[cfg(feature = "std")]
+pub fn secure_getenv_parse<T, K>(key: K) -> Result<T, SecureVarError<T::Err>>
+where
+    T: core::str::FromStr,
+    K: AsRef<std::ffi::OsStr>,
+{
+    secure_getenv_detailed(key)?
+        .parse()
+        .map_err(SecureVarError::Invalid)
+}
+
+/// Get the specified environmental variable as an absolute path (unless the program requires
+/// "secure execution").
+///
+/// The path is rejected if it is relative, contains a NUL byte, or contains a `..` component. It
+/// does not need to be valid Unicode.
+#[cfg(feature = "std")]
+pub fn secure_getenv_path<K: AsRef<std::ffi::OsStr>>(
+    key: K,
+) -> Result<std::path::PathBuf, SecureVarError<InvalidPath>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::path::PathBuf::from(secure_getenv_os_detailed(key)?);
+
+    if path.as_os_str().as_bytes().contains(&0) {
+        Err(SecureVarError::Invalid(InvalidPath::ContainsNul))
+    } else if !path.is_absolute() {
+        Err(SecureVarError::Invalid(InvalidPath::Relative))
+    } else if path
+        .components()
+        .any(|c| c == std::path::Component::ParentDir)
+    {
+        Err(SecureVarError::Invalid(InvalidPath::ParentDir))
+    } else {
+        Ok(path)
+    }
+}
+
+/// Get the specified environmental variable as a boolean (unless the program requires "secure
+/// execution").
+///
+/// `1`, `true`, `yes`, and `on` are recognized as `true`; `0`, `false`, `no`, and `off` are
+/// recognized as `false`. The comparison is case-insensitive, and surrounding whitespace is
+/// ignored.
+#[cfg(feature = "std")]
+pub fn secure_getenv_bool<K: AsRef<std::ffi::OsStr>>(
+    key: K,
+) -> Result<bool, SecureVarError<InvalidBool>> {
+    let value = secure_getenv_detailed(key)?;
+    let value = value.trim();
+
+    for &name in ["1", "true", "yes", "on"].iter() {
+        if value.eq_ignore_ascii_case(name) {
+            return Ok(true);
+        }
+    }
+
+    for &name in ["0", "false", "no", "off"].iter() {
+        if value.eq_ignore_ascii_case(name) {
+            return Ok(false);
+        }
+    }
+
+    Err(SecureVarError::Invalid(InvalidBool))
+}
+
+/// Get the specified environmental variable, split it on `sep`, and parse each item with
+/// `FromStr` (unless the program requires "secure execution").
+///
+/// Empty items are skipped. If any item fails to parse, the first error is returned.
+#[cfg(feature = "std")]
+pub fn secure_getenv_list<T, K>(key: K, sep: char) -> Result<Vec<T>, SecureVarError<T::Err>>
+where
+    T: core::str::FromStr,
+    K: AsRef<std::ffi::OsStr>,
+{
+    secure_getenv_detailed(key)?
+        .split(sep)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(SecureVarError::Invalid))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,4 +580,93 @@ mod tests {
             std::env::var_os("PATH").unwrap()
         );
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_secure_getenv_typed() {
+        assert!(crate::util::run_in_child(|| {
+            std::env::set_var("SECURE_EXEC_TEST_NUM", "42");
+            std::env::set_var("SECURE_EXEC_TEST_PATH", "/usr/bin");
+            std::env::set_var("SECURE_EXEC_TEST_REL", "usr/bin");
+            std::env::set_var("SECURE_EXEC_TEST_PARENT", "/usr/../bin");
+            std::env::set_var("SECURE_EXEC_TEST_BOOL", " Yes ");
+            std::env::set_var("SECURE_EXEC_TEST_LIST", "1,2,,3");
+
+            assert_eq!(
+                secure_getenv_parse::<u32, _>("SECURE_EXEC_TEST_NUM"),
+                Ok(42)
+            );
+            assert!(matches!(
+                secure_getenv_parse::<bool, _>("SECURE_EXEC_TEST_NUM"),
+                Err(SecureVarError::Invalid(_))
+            ));
+            assert_eq!(
+                secure_getenv_parse::<u32, _>("SECURE_EXEC_TEST_NONEXISTENT"),
+                Err(SecureVarError::NotPresent)
+            );
+
+            assert_eq!(
+                secure_getenv_path("SECURE_EXEC_TEST_PATH"),
+                Ok(std::path::PathBuf::from("/usr/bin"))
+            );
+            assert_eq!(
+                secure_getenv_path("SECURE_EXEC_TEST_REL"),
+                Err(SecureVarError::Invalid(InvalidPath::Relative))
+            );
+            assert_eq!(
+                secure_getenv_path("SECURE_EXEC_TEST_PARENT"),
+                Err(SecureVarError::Invalid(InvalidPath::ParentDir))
+            );
+
+            assert_eq!(secure_getenv_bool("SECURE_EXEC_TEST_BOOL"), Ok(true));
+            assert_eq!(
+                secure_getenv_bool("SECURE_EXEC_TEST_NUM"),
+                Err(SecureVarError::Invalid(InvalidBool))
+            );
+
+            assert_eq!(
+                secure_getenv_list::<u32, _>("SECURE_EXEC_TEST_LIST", ','),
+                Ok(vec![1, 2, 3])
+            );
+            assert_eq!(
+                secure_getenv_list::<u32, _>("SECURE_EXEC_TEST_PATH", ','),
+                Err(SecureVarError::Invalid(
+                    "/usr/bin".parse::<u32>().unwrap_err()
+                ))
+            );
+
+            true
+        }));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_secure_getenv_detailed() {
+        assert_eq!(
+            secure_getenv_detailed("PATH").unwrap(),
+            std::env::var("PATH").unwrap()
+        );
+        assert_eq!(
+            secure_getenv_os_detailed("PATH").unwrap(),
+            std::env::var_os("PATH").unwrap()
+        );
+
+        assert_eq!(
+            secure_getenv_detailed("SECURE_EXEC_TEST_NONEXISTENT"),
+            Err(SecureGetenvError::NotPresent)
+        );
+
+        assert_eq!(
+            SecureGetenvError::from(std::env::VarError::NotPresent),
+            SecureGetenvError::NotPresent
+        );
+        assert_eq!(
+            std::env::VarError::from(SecureGetenvError::Suppressed(SecureReason::SETUID)),
+            std::env::VarError::NotPresent
+        );
+        assert_eq!(
+            SecureGetenvError::Suppressed(SecureReason::SETUID).to_string(),
+            "environment variable ignored because of secure execution (SETUID)"
+        );
+    }
 }
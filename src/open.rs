@@ -0,0 +1,578 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Apply, Errno, TrustError, TrustErrorKind, TrustPolicy};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        /// The flags used to open intermediate directories. `O_PATH` means no read permission is
+        /// needed, and combining `O_NOFOLLOW` with `O_DIRECTORY` means symbolic links are
+        /// rejected (with `ENOTDIR`) instead of being opened themselves.
+        const DIR_FLAGS: libc::c_int =
+            libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    } else {
+        /// The flags used to open intermediate directories.
+        const DIR_FLAGS: libc::c_int =
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod openat2 {
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    use crate::Errno;
+
+    #[repr(C)]
+    struct OpenHow {
+        flags: u64,
+        mode: u64,
+        resolve: u64,
+    }
+
+    pub(super) const UNKNOWN: u8 = 0;
+    pub(super) const SUPPORTED: u8 = 1;
+    pub(super) const UNSUPPORTED: u8 = 2;
+
+    /// Whether `openat2()` is usable. This is determined by the first call: besides `ENOSYS`,
+    /// seccomp filters (for example, in containers) often make it fail with `EPERM`, and `E2BIG`
+    /// means the kernel doesn't understand `struct open_how`.
+    pub(super) static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+    /// Open `name` relative to `dirfd` with `openat2()`, refusing to follow any symbolic links
+    /// (or "magic links") or to escape `dirfd`. Returns `None` if `openat2()` is not supported.
+    pub(super) fn openat2(
+        dirfd: libc::c_int,
+        name: &core::ffi::CStr,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+        no_xdev: bool,
+    ) -> Option<Result<libc::c_int, Errno>> {
+        let state = STATE.load(Ordering::Relaxed);
+        if state == UNSUPPORTED {
+            return None;
+        }
+
+        let mut resolve =
+            libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS | libc::RESOLVE_BENEATH;
+        if no_xdev {
+            resolve |= libc::RESOLVE_NO_XDEV;
+        }
+
+        let how = OpenHow {
+            flags: flags as u64,
+            // The mode must be 0 unless a file is being created (note that O_TMPFILE includes
+            // O_DIRECTORY)
+            mode: if flags & libc::O_CREAT != 0 || flags & libc::O_TMPFILE == libc::O_TMPFILE {
+                mode as u64
+            } else {
+                0
+            },
+            resolve,
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                dirfd,
+                name.as_ptr(),
+                &how as *const OpenHow,
+                core::mem::size_of::<OpenHow>(),
+            )
+        };
+
+        let errno = Errno::last();
+        let unsupported = fd < 0
+            && (errno.raw() == libc::ENOSYS
+                || state == UNKNOWN && (errno.raw() == libc::EPERM || errno.raw() == libc::E2BIG));
+
+        if unsupported {
+            STATE.store(UNSUPPORTED, Ordering::Relaxed);
+            return None;
+        }
+        if state == UNKNOWN {
+            STATE.store(SUPPORTED, Ordering::Relaxed);
+        }
+
+        if fd >= 0 {
+            Some(Ok(fd as libc::c_int))
+        } else {
+            Some(Err(errno))
+        }
+    }
+}
+
+/// Options for opening a file while verifying that no untrusted user could have tampered with it.
+///
+/// If the program requires "secure execution" (or if [`apply()`] is set to [`Apply::Always`]),
+/// [`open()`] resolves the path one component at a time, starting from `/`:
+///
+/// - Each component is opened relative to the file descriptor of the previous one. On Linux 5.6+,
+///   this uses `openat2()` with `RESOLVE_NO_SYMLINKS | RESOLVE_NO_MAGICLINKS | RESOLVE_BENEATH`;
+///   elsewhere, it uses `openat()` with `O_NOFOLLOW`. Either way, symbolic links are never
+///   followed, and paths containing `..` are rejected.
+/// - Each opened component is checked with `fstat()` against a [`TrustPolicy`], using the same
+///   rules as [`verify_trusted_path()`].
+/// - The final component is first opened with `O_NONBLOCK | O_NOCTTY` (so that, for example, a
+///   FIFO planted in its place cannot block the call), and with `O_TRUNC` held back. Only once it
+///   has passed the check is it truncated (if requested) and switched back to blocking mode. If
+///   `O_CREAT` is given and the file does not exist, it is created with `O_EXCL`.
+///
+/// Since every check is performed on a file descriptor that is then used for the next step, there
+/// is no window in which the path could be swapped out from under the check.
+///
+/// Otherwise, [`open()`] simply opens the path with `open()`.
+///
+/// ```no_run
+/// use secure_exec::{SecureOpenOptions, TrustPolicy};
+///
+/// let file = SecureOpenOptions::new()
+///     .policy(TrustPolicy::new().allow_sticky(true))
+///     .open("/etc/myapp.conf")
+///     .unwrap();
+/// ```
+///
+/// [`apply()`]: #method.apply
+/// [`open()`]: #method.open
+/// [`Apply::Always`]: ./enum.Apply.html#variant.Always
+/// [`TrustPolicy`]: ./struct.TrustPolicy.html
+/// [`verify_trusted_path()`]: ./fn.verify_trusted_path.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SecureOpenOptions {
+    flags: libc::c_int,
+    mode: libc::mode_t,
+    policy: TrustPolicy,
+    apply: Apply,
+    same_filesystem: bool,
+}
+
+impl SecureOpenOptions {
+    /// Create a new set of options for opening a file read-only, trusting only `root`.
+    pub fn new() -> Self {
+        Self {
+            flags: libc::O_RDONLY,
+            mode: 0o600,
+            policy: TrustPolicy::new(),
+            apply: Apply::IfSecure,
+            same_filesystem: false,
+        }
+    }
+
+    /// Set the flags passed to `open()` for the final component (for example,
+    /// `libc::O_WRONLY | libc::O_APPEND`). `O_CLOEXEC` is always added, and `O_NOFOLLOW` is added
+    /// when opening strictly (see above for how `O_TRUNC` and `O_CREAT` are then handled).
+    /// (Default: `O_RDONLY`.)
+    pub fn flags(mut self, flags: libc::c_int) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the mode used if the file is created (with `O_CREAT`). (Default: `0o600`.)
+    pub fn mode(mut self, mode: libc::mode_t) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the policy used to check each component.
+    pub fn policy(mut self, policy: TrustPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set when the strict component-by-component resolution is used. (Default:
+    /// [`Apply::IfSecure`].)
+    ///
+    /// [`Apply::IfSecure`]: ./enum.Apply.html#variant.IfSecure
+    pub fn apply(mut self, apply: Apply) -> Self {
+        self.apply = apply;
+        self
+    }
+
+    /// Require every component to be on the same filesystem as `/` (with `RESOLVE_NO_XDEV` where
+    /// `openat2()` is available). (Default: `false`.)
+    pub fn same_filesystem(mut self, same: bool) -> Self {
+        self.same_filesystem = same;
+        self
+    }
+
+    /// Open the file at `path` with these options.
+    ///
+    /// If strict resolution is used and it fails, the returned error names the (partial) path of
+    /// the component that could not be opened or that failed verification.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<File, TrustError> {
+        let path = path.as_ref();
+
+        if self.apply.should_apply() {
+            return self.open_strict(path);
+        }
+
+        let cpath = to_cstring(path.as_os_str().as_bytes(), path)?;
+        let fd = unsafe {
+            libc::open(
+                cpath.as_ptr(),
+                self.flags | libc::O_CLOEXEC,
+                self.mode as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(TrustError::new(
+                path.into(),
+                TrustErrorKind::Os(Errno::last()),
+            ));
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn open_strict(&self, path: &Path) -> Result<File, TrustError> {
+        let path = if path.is_relative() {
+            std::env::current_dir()
+                .map_err(|e| {
+                    TrustError::new(
+                        ".".into(),
+                        TrustErrorKind::Os(Errno::from_raw(e.raw_os_error().unwrap_or(libc::EIO))),
+                    )
+                })?
+                .join(path)
+        } else {
+            path.to_path_buf()
+        };
+
+        let mut names = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => names.push(name),
+                Component::ParentDir => {
+                    return Err(TrustError::new(path.clone(), TrustErrorKind::ParentDir))
+                }
+                Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+            }
+        }
+
+        let mut current = PathBuf::from("/");
+        let flags = if names.is_empty() {
+            self.flags | libc::O_CLOEXEC
+        } else {
+            DIR_FLAGS
+        };
+        let fd = unsafe { libc::open(b"/\0".as_ptr() as *const libc::c_char, flags, 0) };
+        if fd < 0 {
+            return Err(TrustError::new(current, TrustErrorKind::Os(Errno::last())));
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let mut dev = self.check(&file, &current, None)?;
+
+        for (i, name) in names.iter().enumerate() {
+            current.push(name);
+            let last = i == names.len() - 1;
+
+            let cname = to_cstring(name.as_bytes(), &current)?;
+            let next = if last {
+                self.open_last(file.as_raw_fd(), &cname)
+            } else {
+                self.openat(file.as_raw_fd(), &cname, DIR_FLAGS)
+            }
+            .map_err(|errno| TrustError::new(current.clone(), TrustErrorKind::Os(errno)))?;
+
+            dev = self.check(&next, &current, Some(dev))?;
+            file = next;
+        }
+
+        if !names.is_empty() {
+            self.finish_last(&file)
+                .map_err(|errno| TrustError::new(current, TrustErrorKind::Os(errno)))?;
+        }
+
+        Ok(file)
+    }
+
+    /// Open the final component relative to `dirfd` without blocking, and without any of the side
+    /// effects of `O_TRUNC` (see [`finish_last()`](#method.finish_last)).
+    fn open_last(&self, dirfd: libc::c_int, name: &CString) -> Result<File, Errno> {
+        const EXCL: libc::c_int = libc::O_CREAT | libc::O_EXCL;
+
+        let flags = (self.flags & !(EXCL | libc::O_TRUNC))
+            | libc::O_NONBLOCK
+            | libc::O_NOCTTY
+            | libc::O_NOFOLLOW
+            | libc::O_CLOEXEC;
+
+        loop {
+            if self.flags & EXCL != EXCL {
+                match self.openat(dirfd, name, flags) {
+                    // It doesn't exist yet; create it below
+                    Err(errno)
+                        if errno.raw() == libc::ENOENT && self.flags & libc::O_CREAT != 0 => {}
+                    res => return res,
+                }
+            }
+
+            match self.openat(dirfd, name, flags | EXCL) {
+                // Someone else created it in the meantime; open that instead
+                Err(errno) if errno.raw() == libc::EEXIST && self.flags & libc::O_EXCL == 0 => {}
+                res => return res,
+            }
+        }
+    }
+
+    /// Apply the parts of `self.flags` that `open_last()` held back, now that the file has been
+    /// verified.
+    fn finish_last(&self, file: &File) -> Result<(), Errno> {
+        // As with open(), O_TRUNC only affects regular files
+        if self.flags & libc::O_TRUNC != 0 && self.flags & libc::O_ACCMODE != libc::O_RDONLY {
+            let meta = file
+                .metadata()
+                .map_err(|e| Errno::from_raw(e.raw_os_error().unwrap_or(libc::EIO)))?;
+            if meta.is_file() && unsafe { libc::ftruncate(file.as_raw_fd(), 0) } != 0 {
+                return Err(Errno::last());
+            }
+        }
+
+        if self.flags & libc::O_NONBLOCK == 0 {
+            let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+            if flags < 0
+                || unsafe {
+                    libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags & !libc::O_NONBLOCK)
+                } != 0
+            {
+                return Err(Errno::last());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn openat(
+        &self,
+        dirfd: libc::c_int,
+        name: &CString,
+        flags: libc::c_int,
+    ) -> Result<File, Errno> {
+        #[cfg(target_os = "linux")]
+        if let Some(res) = openat2::openat2(dirfd, name, flags, self.mode, self.same_filesystem) {
+            return res.map(|fd| unsafe { File::from_raw_fd(fd) });
+        }
+
+        let fd = unsafe {
+            libc::openat(
+                dirfd,
+                name.as_ptr(),
+                flags | libc::O_NOFOLLOW,
+                self.mode as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(Errno::last());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Check the file open at `file` against the policy (and, if requested, that it is on the
+    /// device `parent_dev`). Returns its device number.
+    // `mode_t` and `dev_t` do not match the `stat` fields on every platform
+    #[allow(clippy::unnecessary_cast)]
+    fn check(&self, file: &File, path: &Path, parent_dev: Option<u64>) -> Result<u64, TrustError> {
+        let mut st = unsafe { core::mem::zeroed::<libc::stat>() };
+        if unsafe { libc::fstat(file.as_raw_fd(), &mut st) } != 0 {
+            return Err(TrustError::new(
+                path.into(),
+                TrustErrorKind::Os(Errno::last()),
+            ));
+        }
+
+        if self.same_filesystem && parent_dev.is_some_and(|dev| dev != st.st_dev as u64) {
+            return Err(TrustError::new(
+                path.into(),
+                TrustErrorKind::CrossesFilesystem,
+            ));
+        }
+
+        self.policy
+            .check(st.st_uid, st.st_gid, st.st_mode as u32)
+            .map_err(|kind| TrustError::new(path.into(), kind))?;
+
+        Ok(st.st_dev as u64)
+    }
+}
+
+impl Default for SecureOpenOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn to_cstring(bytes: &[u8], path: &Path) -> Result<CString, TrustError> {
+    CString::new(bytes).map_err(|_| {
+        TrustError::new(
+            path.into(),
+            TrustErrorKind::Os(Errno::from_raw(libc::EINVAL)),
+        )
+    })
+}
+
+/// Open the file at `path` for reading with the default [`SecureOpenOptions`] (which only trust
+/// `root`, and only resolve the path strictly if [`is_secure()`] returns `true`).
+///
+/// [`SecureOpenOptions`]: ./struct.SecureOpenOptions.html
+/// [`is_secure()`]: ./fn.is_secure.html
+#[inline]
+pub fn secure_open<P: AsRef<Path>>(path: P) -> Result<File, TrustError> {
+    SecureOpenOptions::new().open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::util::TempDir;
+
+    fn check_open(dir: &Path) -> bool {
+        let uid = unsafe { libc::geteuid() };
+        let opts = SecureOpenOptions::new()
+            .policy(TrustPolicy::new().trust_uid(uid).allow_sticky(true))
+            .apply(Apply::Always);
+
+        let mut data = String::new();
+        opts.open(dir.join("file"))
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+
+        // The error for opening a symlink with O_NOFOLLOW varies
+        #[cfg(target_os = "freebsd")]
+        let nofollow_errno = libc::EMLINK;
+        #[cfg(target_os = "netbsd")]
+        let nofollow_errno = libc::EFTYPE;
+        #[cfg(not(any(target_os = "freebsd", target_os = "netbsd")))]
+        let nofollow_errno = libc::ELOOP;
+
+        let err = opts.open(dir.join("link")).unwrap_err();
+        let err2 = opts.open(dir.join("../").join(dir.file_name().unwrap()).join("file"));
+
+        data == "data"
+            && err.kind() == TrustErrorKind::Os(Errno::from_raw(nofollow_errno))
+            && err.path().ends_with("link")
+            && err2.unwrap_err().kind() == TrustErrorKind::ParentDir
+            && opts.open("/").is_ok()
+            && opts
+                .clone()
+                .apply(Apply::IfSecure)
+                .open(dir.join("link"))
+                .is_ok()
+    }
+
+    #[test]
+    fn test_secure_open() {
+        let dir = TempDir::new("open");
+        std::fs::write(dir.path().join("file"), b"data").unwrap();
+        std::fs::set_permissions(
+            dir.path().join("file"),
+            std::fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(dir.path().join("file"), dir.path().join("link")).unwrap();
+
+        assert!(check_open(dir.path()));
+
+        #[cfg(target_os = "linux")]
+        assert!(crate::util::run_in_child(|| {
+            openat2::STATE.store(openat2::UNSUPPORTED, core::sync::atomic::Ordering::Relaxed);
+            check_open(dir.path())
+        }));
+
+        std::fs::set_permissions(
+            dir.path().join("file"),
+            std::fs::Permissions::from_mode(0o666),
+        )
+        .unwrap();
+        let err = SecureOpenOptions::new()
+            .policy(
+                TrustPolicy::new()
+                    .trust_uid(unsafe { libc::geteuid() })
+                    .allow_sticky(true),
+            )
+            .apply(Apply::Always)
+            .open(dir.path().join("file"))
+            .unwrap_err();
+        assert_eq!(err.kind(), TrustErrorKind::WorldWritable);
+        assert!(err.path().ends_with("file"));
+    }
+
+    fn check_open_flags(dir: &Path) -> bool {
+        let uid = unsafe { libc::geteuid() };
+        let opts = SecureOpenOptions::new()
+            .policy(TrustPolicy::new().trust_uid(uid).allow_sticky(true))
+            .apply(Apply::Always);
+        let nonblocking =
+            |file: &File| unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) } & libc::O_NONBLOCK;
+
+        std::fs::write(dir.join("trusted"), b"data").unwrap();
+        let _ = std::fs::remove_file(dir.join("new"));
+
+        // Rejected files are neither truncated nor waited on
+        let untrusted = opts
+            .clone()
+            .flags(libc::O_WRONLY | libc::O_TRUNC)
+            .open(dir.join("untrusted"))
+            .unwrap_err();
+        let fifo = opts.open(dir.join("fifo")).unwrap_err();
+
+        let trusted = opts
+            .clone()
+            .flags(libc::O_WRONLY | libc::O_TRUNC)
+            .open(dir.join("trusted"))
+            .unwrap();
+        let new = opts
+            .clone()
+            .flags(libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL)
+            .mode(0o644)
+            .open(dir.join("new"))
+            .unwrap();
+        let exists = opts
+            .clone()
+            .flags(libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL)
+            .open(dir.join("new"))
+            .unwrap_err();
+
+        untrusted.kind() == TrustErrorKind::WorldWritable
+            && std::fs::read(dir.join("untrusted")).unwrap() == b"data"
+            && fifo.kind() == TrustErrorKind::WorldWritable
+            && trusted.metadata().unwrap().len() == 0
+            && nonblocking(&trusted) == 0
+            && nonblocking(&new) == 0
+            && exists.kind() == TrustErrorKind::Os(Errno::from_raw(libc::EEXIST))
+    }
+
+    #[test]
+    fn test_secure_open_flags() {
+        let dir = TempDir::new("open_flags");
+        std::fs::write(dir.path().join("untrusted"), b"data").unwrap();
+        std::fs::set_permissions(
+            dir.path().join("untrusted"),
+            std::fs::Permissions::from_mode(0o666),
+        )
+        .unwrap();
+
+        let fifo = CString::new(dir.path().join("fifo").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        std::fs::set_permissions(
+            dir.path().join("fifo"),
+            std::fs::Permissions::from_mode(0o666),
+        )
+        .unwrap();
+
+        assert!(check_open_flags(dir.path()));
+
+        #[cfg(target_os = "linux")]
+        assert!(crate::util::run_in_child(|| {
+            openat2::STATE.store(openat2::UNSUPPORTED, core::sync::atomic::Ordering::Relaxed);
+            check_open_flags(dir.path())
+        }));
+    }
+}
@@ -0,0 +1,596 @@
+use core::fmt;
+
+use crate::creds::{getresgid, getresuid};
+use crate::Errno;
+
+/// An error returned by [`drop_privileges_permanently()`] and [`drop_privileges_to()`].
+///
+/// [`drop_privileges_permanently()`]: ./fn.drop_privileges_permanently.html
+/// [`drop_privileges_to()`]: ./fn.drop_privileges_to.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DropError {
+    /// A system call failed.
+    Os {
+        /// The name of the function that failed (for example, `"setresuid"`).
+        op: &'static str,
+        /// The error it failed with.
+        errno: Errno,
+    },
+    /// After the switch, the real, effective, and saved UIDs were not all set to the target UID.
+    UidMismatch {
+        /// The UID that all three should have been set to.
+        expected: libc::uid_t,
+        /// The actual real UID.
+        real: libc::uid_t,
+        /// The actual effective UID.
+        effective: libc::uid_t,
+        /// The actual saved UID.
+        saved: libc::uid_t,
+    },
+    /// After the switch, the real, effective, and saved GIDs were not all set to the target GID.
+    GidMismatch {
+        /// The GID that all three should have been set to.
+        expected: libc::gid_t,
+        /// The actual real GID.
+        real: libc::gid_t,
+        /// The actual effective GID.
+        effective: libc::gid_t,
+        /// The actual saved GID.
+        saved: libc::gid_t,
+    },
+    /// After the switch, the supplementary group list contained a group other than the target
+    /// GID.
+    GroupsMismatch,
+    /// After the switch, an attempt to regain the previous UID succeeded.
+    RegainedUid(libc::uid_t),
+    /// After the switch, an attempt to regain the previous GID succeeded.
+    RegainedGid(libc::gid_t),
+}
+
+impl fmt::Display for DropError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Os { op, errno } => write!(f, "{}() failed: {}", op, errno),
+            Self::UidMismatch {
+                expected,
+                real,
+                effective,
+                saved,
+            } => write!(
+                f,
+                "expected UIDs to be {}, but got real={} effective={} saved={}",
+                expected, real, effective, saved
+            ),
+            Self::GidMismatch {
+                expected,
+                real,
+                effective,
+                saved,
+            } => write!(
+                f,
+                "expected GIDs to be {}, but got real={} effective={} saved={}",
+                expected, real, effective, saved
+            ),
+            Self::GroupsMismatch => f.write_str("supplementary groups were not cleared"),
+            Self::RegainedUid(uid) => write!(f, "was able to regain UID {}", uid),
+            Self::RegainedGid(gid) => write!(f, "was able to regain GID {}", gid),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DropError {}
+
+#[inline]
+fn check(op: &'static str, ret: libc::c_int) -> Result<(), DropError> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(DropError::Os {
+            op,
+            errno: Errno::last(),
+        })
+    }
+}
+
+#[allow(clippy::needless_return)]
+fn setresgid(gid: libc::gid_t) -> Result<(), DropError> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+        ))] {
+            return check("setresgid", unsafe { libc::setresgid(gid, gid, gid) });
+        } else {
+            check("setregid", unsafe { libc::setregid(gid, gid) })?;
+            return check("setgid", unsafe { libc::setgid(gid) });
+        }
+    }
+}
+
+#[allow(clippy::needless_return)]
+fn setresuid(uid: libc::uid_t) -> Result<(), DropError> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "openbsd",
+        ))] {
+            return check("setresuid", unsafe { libc::setresuid(uid, uid, uid) });
+        } else {
+            check("setreuid", unsafe { libc::setreuid(uid, uid) })?;
+            return check("setuid", unsafe { libc::setuid(uid) });
+        }
+    }
+}
+
+/// Check that the supplementary group list is empty or contains only `gid`.
+fn groups_cleared(gid: libc::gid_t) -> bool {
+    let mut groups = [0; 2];
+    let n = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+
+    n >= 0 && groups[..n as usize].iter().all(|&g| g == gid)
+}
+
+/// Permanently drop privileges by switching to the real UID/GID recorded by
+/// [`initial_credentials()`].
+///
+/// This is equivalent to `drop_privileges_to(creds.ruid(), creds.rgid())`; see
+/// [`drop_privileges_to()`] for details.
+///
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+/// [`drop_privileges_to()`]: ./fn.drop_privileges_to.html
+pub fn drop_privileges_permanently() -> Result<(), DropError> {
+    let creds = crate::initial_credentials();
+    drop_privileges_to(creds.ruid(), creds.rgid())
+}
+
+/// Permanently switch to the given UID and GID, and verify that the previous IDs cannot be
+/// regained.
+///
+/// This performs the following steps, in order:
+///
+/// 1. If the process is running as root, replace the supplementary group list with `gid`.
+/// 2. Set the real, effective, and saved GIDs to `gid` (with `setresgid()` where available;
+///    otherwise with `setregid()` and `setgid()`).
+/// 3. Set the real, effective, and saved UIDs to `uid` (with `setresuid()` where available;
+///    otherwise with `setreuid()` and `setuid()`).
+/// 4. Verify that all of the UIDs and GIDs (and, if step 1 was performed, the supplementary group
+///    list) were changed.
+/// 5. Verify that attempts to switch back to root or to any of the previous UIDs/GIDs fail.
+///
+/// If any step fails, an error is returned. In that case the process may be left with a
+/// partially changed set of credentials, so the caller should usually exit immediately.
+///
+/// As with any credential change, this should be done before spawning other threads (C libraries
+/// generally apply the change to all threads, but doing so while other threads are running is
+/// fragile).
+pub fn drop_privileges_to(uid: libc::uid_t, gid: libc::gid_t) -> Result<(), DropError> {
+    // Make sure the caches reflect the original state
+    crate::is_secure();
+    crate::initial_credentials();
+
+    let old_uids = getresuid();
+    let old_gids = getresgid();
+
+    let cleared_groups = old_uids.1 == 0;
+    if cleared_groups {
+        check("setgroups", unsafe { libc::setgroups(1, &gid) })?;
+    }
+
+    setresgid(gid)?;
+    setresuid(uid)?;
+
+    let (real, effective, saved) = getresuid();
+    if (real, effective, saved) != (uid, uid, uid) {
+        return Err(DropError::UidMismatch {
+            expected: uid,
+            real,
+            effective,
+            saved,
+        });
+    }
+
+    let (real, effective, saved) = getresgid();
+    if (real, effective, saved) != (gid, gid, gid) {
+        return Err(DropError::GidMismatch {
+            expected: gid,
+            real,
+            effective,
+            saved,
+        });
+    }
+
+    if cleared_groups && !groups_cleared(gid) {
+        return Err(DropError::GroupsMismatch);
+    }
+
+    if uid != 0 {
+        for &old_uid in [0, old_uids.0, old_uids.1, old_uids.2].iter() {
+            if old_uid != uid && unsafe { libc::setreuid(old_uid, old_uid) } == 0 {
+                return Err(DropError::RegainedUid(old_uid));
+            }
+            if old_uid != uid && unsafe { libc::seteuid(old_uid) } == 0 {
+                return Err(DropError::RegainedUid(old_uid));
+            }
+        }
+
+        for &old_gid in [old_gids.0, old_gids.1, old_gids.2].iter() {
+            if old_gid != gid && unsafe { libc::setegid(old_gid) } == 0 {
+                return Err(DropError::RegainedGid(old_gid));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug)]
+enum TempMode {
+    Effective,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Filesystem,
+}
+
+/// A guard that temporarily switches the process's effective (or, on Linux, filesystem) UID and
+/// GID, and restores them when dropped.
+///
+/// Unlike [`drop_privileges_to()`], this leaves the saved set-user-ID and set-group-ID alone, so
+/// the original IDs can be restored later. It is intended for operations such as opening a
+/// user-supplied file with the permissions of the user who invoked a set-UID program.
+///
+/// Before switching IDs, this primes the caches used by [`is_secure()`], [`secure_reason()`], and
+/// [`initial_credentials()`], so their results are not affected by the switch.
+///
+/// This type is not `Send`, since on Linux the filesystem UID/GID are per-thread and must be
+/// restored on the same thread.
+///
+/// [`drop_privileges_to()`]: ./fn.drop_privileges_to.html
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+#[derive(Debug)]
+pub struct TemporaryDrop {
+    mode: TempMode,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    restored: bool,
+    _not_send: core::marker::PhantomData<*const ()>,
+}
+
+impl TemporaryDrop {
+    /// Set the effective UID and GID to the current real UID and GID.
+    #[inline]
+    pub fn new() -> Result<Self, Errno> {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Self::with_ids(uid, gid)
+    }
+
+    /// Set the effective UID and GID to the given values.
+    ///
+    /// Note that the effective UID/GID is a process-wide attribute; most C libraries apply the
+    /// change to every thread.
+    pub fn with_ids(uid: libc::uid_t, gid: libc::gid_t) -> Result<Self, Errno> {
+        prime_caches();
+
+        let (old_uid, old_gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        if unsafe { libc::setegid(gid) } != 0 {
+            return Err(Errno::last());
+        }
+
+        if unsafe { libc::seteuid(uid) } != 0 {
+            let err = Errno::last();
+            unsafe {
+                libc::setegid(old_gid);
+            }
+            return Err(err);
+        }
+
+        Ok(Self::from_old(TempMode::Effective, old_uid, old_gid))
+    }
+
+    /// Set the filesystem UID and GID (which are used for file permission checks) to the current
+    /// real UID and GID.
+    ///
+    /// Unlike the effective UID/GID, the filesystem UID/GID are per-thread, and they do not
+    /// affect other permission checks (such as whether other processes may send signals to this
+    /// one).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[inline]
+    pub fn fs_only() -> Result<Self, Errno> {
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Self::fs_only_with_ids(uid, gid)
+    }
+
+    /// Set the filesystem UID and GID to the given values.
+    ///
+    /// See [`fs_only()`](#method.fs_only) for more information.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn fs_only_with_ids(uid: libc::uid_t, gid: libc::gid_t) -> Result<Self, Errno> {
+        prime_caches();
+
+        let old_gid = setfsgid(gid)?;
+
+        let old_uid = match setfsuid(uid) {
+            Ok(old_uid) => old_uid,
+            Err(err) => {
+                let _ = setfsgid(old_gid);
+                return Err(err);
+            }
+        };
+
+        Ok(Self::from_old(TempMode::Filesystem, old_uid, old_gid))
+    }
+
+    #[inline]
+    fn from_old(mode: TempMode, uid: libc::uid_t, gid: libc::gid_t) -> Self {
+        Self {
+            mode,
+            uid,
+            gid,
+            restored: false,
+            _not_send: core::marker::PhantomData,
+        }
+    }
+
+    /// Restore the original IDs, reporting any errors.
+    ///
+    /// Dropping the guard also restores the original IDs, but ignores errors.
+    #[inline]
+    pub fn restore(mut self) -> Result<(), Errno> {
+        self.restore_impl()
+    }
+
+    fn restore_impl(&mut self) -> Result<(), Errno> {
+        if self.restored {
+            return Ok(());
+        }
+        self.restored = true;
+
+        match self.mode {
+            TempMode::Effective => unsafe {
+                // Restore the UID first so we have permission to restore the GID
+                if libc::seteuid(self.uid) != 0 || libc::setegid(self.gid) != 0 {
+                    return Err(Errno::last());
+                }
+            },
+
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            TempMode::Filesystem => {
+                setfsuid(self.uid)?;
+                setfsgid(self.gid)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryDrop {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = self.restore_impl();
+    }
+}
+
+/// Open the file at `path` with the permissions of the user who executed this program.
+///
+/// This answers the question "could the invoking user open this file?" without the race
+/// conditions inherent in `access()`: it temporarily switches to the real UID and GID recorded
+/// by [`initial_credentials()`] (using the filesystem UID/GID on Linux, or the effective UID/GID
+/// elsewhere), opens the file with the given `flags` (plus `O_CLOEXEC`), and then switches back.
+/// If the file is created, it is created with mode `0o666` (minus the umask).
+///
+/// The original IDs are restored by a [`TemporaryDrop`] guard, so they are restored even if this
+/// function panics. Note that the supplementary groups are not changed, so they still apply to
+/// the permission checks. (This is usually correct, since executing a set-UID program does not
+/// change the supplementary groups.)
+///
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+/// [`TemporaryDrop`]: ./struct.TemporaryDrop.html
+#[cfg(feature = "std")]
+pub fn open_as_real_user<P: AsRef<std::path::Path>>(
+    path: P,
+    flags: libc::c_int,
+) -> Result<std::fs::File, Errno> {
+    let creds = crate::initial_credentials();
+    open_as(path.as_ref(), flags, creds.ruid(), creds.rgid())
+}
+
+#[cfg(feature = "std")]
+fn open_as(
+    path: &std::path::Path,
+    flags: libc::c_int,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+) -> Result<std::fs::File, Errno> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::FromRawFd;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Errno::from_raw(libc::EINVAL))?;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let guard = TemporaryDrop::fs_only_with_ids(uid, gid)?;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let guard = TemporaryDrop::with_ids(uid, gid)?;
+
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            flags | libc::O_CLOEXEC,
+            0o666 as libc::c_uint,
+        )
+    };
+    let err = Errno::last();
+
+    if let Err(restore_err) = guard.restore() {
+        if fd >= 0 {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+        return Err(restore_err);
+    }
+
+    if fd < 0 {
+        return Err(err);
+    }
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+fn prime_caches() {
+    crate::is_secure();
+    crate::secure_reason();
+    crate::initial_credentials();
+}
+
+/// Call `setfsuid()`, returning the old filesystem UID or an error if the change did not take
+/// effect.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn setfsuid(uid: libc::uid_t) -> Result<libc::uid_t, Errno> {
+    let old = unsafe { libc::setfsuid(uid) } as libc::uid_t;
+
+    // setfsuid() doesn't report errors, but passing an invalid ID returns the current value
+    if unsafe { libc::setfsuid(libc::uid_t::MAX) } as libc::uid_t == uid {
+        Ok(old)
+    } else {
+        Err(Errno::from_raw(libc::EPERM))
+    }
+}
+
+/// Call `setfsgid()`, returning the old filesystem GID or an error if the change did not take
+/// effect.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn setfsgid(gid: libc::gid_t) -> Result<libc::gid_t, Errno> {
+    let old = unsafe { libc::setfsgid(gid) } as libc::gid_t;
+
+    if unsafe { libc::setfsgid(libc::gid_t::MAX) } as libc::gid_t == gid {
+        Ok(old)
+    } else {
+        Err(Errno::from_raw(libc::EPERM))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_drop_privileges_noop() {
+        // "Dropping" to our current IDs should always work
+        assert!(run_in_child(|| {
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            drop_privileges_permanently().is_ok()
+                && getresuid() == (uid, uid, uid)
+                && getresgid() == (gid, gid, gid)
+        }));
+    }
+
+    #[test]
+    fn test_drop_privileges_to() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        assert!(run_in_child(|| {
+            drop_privileges_to(65534, 65534).is_ok()
+                && getresuid() == (65534, 65534, 65534)
+                && getresgid() == (65534, 65534, 65534)
+                && groups_cleared(65534)
+                && unsafe { libc::setuid(0) } != 0
+        }));
+    }
+
+    #[test]
+    fn test_drop_privileges_error() {
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        // An unprivileged process can't switch to another user
+        assert!(run_in_child(|| matches!(
+            drop_privileges_to(0, 0),
+            Err(DropError::Os { .. })
+        )));
+    }
+
+    #[test]
+    fn test_temporary_drop() {
+        if unsafe { libc::geteuid() } != 0 {
+            // We can't switch to another user, but a no-op switch should work
+            let guard = TemporaryDrop::new().unwrap();
+            assert_eq!(unsafe { libc::geteuid() }, unsafe { libc::getuid() });
+            guard.restore().unwrap();
+            return;
+        }
+
+        assert!(run_in_child(|| {
+            let secure = crate::is_secure();
+
+            let guard = TemporaryDrop::with_ids(65534, 65534).unwrap();
+            let dropped = getresuid() == (0, 65534, 0)
+                && getresgid() == (0, 65534, 0)
+                && crate::is_secure() == secure
+                && crate::initial_credentials().euid() == 0;
+            drop(guard);
+
+            dropped && getresuid() == (0, 0, 0) && getresgid() == (0, 0, 0)
+        }));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_open_as_real_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::util::TempDir::new("open_as");
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let res = open_as_real_user(&path, libc::O_RDONLY).map(drop);
+        let dropped = unsafe { libc::geteuid() } == 0
+            && run_in_child(|| {
+                let res = open_as(&path, libc::O_RDONLY, 65534, 65534).map(drop);
+                res == Err(Errno::from_raw(libc::EACCES))
+                    && getresuid() == (0, 0, 0)
+                    && open_as(&path, libc::O_RDONLY, 0, 0).is_ok()
+            });
+
+        assert_eq!(res, Ok(()));
+        assert_eq!(dropped, unsafe { libc::geteuid() } == 0);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_temporary_drop_fs_only() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        // Passing an invalid ID returns the current value without changing it
+        let fsids = || unsafe {
+            (
+                libc::setfsuid(libc::uid_t::MAX) as libc::uid_t,
+                libc::setfsgid(libc::gid_t::MAX) as libc::gid_t,
+            )
+        };
+
+        assert!(run_in_child(|| {
+            let guard = TemporaryDrop::fs_only_with_ids(65534, 65534).unwrap();
+            let dropped = fsids() == (65534, 65534) && getresuid() == (0, 0, 0);
+            guard.restore().unwrap();
+
+            dropped && fsids() == (0, 0)
+        }));
+    }
+}
@@ -0,0 +1,408 @@
+use core::fmt;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+
+/// A set of flags describing *why* the current process requires "secure execution".
+///
+/// This is returned by [`secure_reason()`] and [`secure_reason_uncached()`] (and, with a
+/// different set of flags, by [`ungranted_privileges()`]). It behaves like a small bitflags type:
+/// flags can be combined with `|` and tested with [`contains()`].
+///
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`secure_reason_uncached()`]: ./fn.secure_reason_uncached.html
+/// [`ungranted_privileges()`]: ./fn.ungranted_privileges.html
+/// [`contains()`]: #method.contains
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SecureReason(u8);
+
+impl SecureReason {
+    /// The effective UID differs from the real UID (usually because the binary is set-UID).
+    pub const SETUID: Self = Self(0x01);
+    /// The effective GID differs from the real GID (usually because the binary is set-GID).
+    pub const SETGID: Self = Self(0x02);
+    /// The process gained capabilities from Linux file capabilities.
+    pub const FILE_CAPS: Self = Self(0x04);
+    /// The process is "secure" but its credentials are unchanged, which on Linux usually means
+    /// that a Linux Security Module (SELinux, AppArmor, etc.) requested secure execution during a
+    /// domain transition.
+    pub const LSM_TRANSITION: Self = Self(0x08);
+    /// The process is "secure" but the reason could not be determined (for example, because the
+    /// process's credentials were changed before the first check).
+    pub const UNKNOWN: Self = Self(0x10);
+    /// The executable is set-UID, but executing it did not change the effective UID (for example,
+    /// because `no_new_privs` is set, or because the filesystem is mounted `nosuid`).
+    ///
+    /// This is only reported by [`ungranted_privileges()`].
+    ///
+    /// [`ungranted_privileges()`]: ./fn.ungranted_privileges.html
+    pub const SETUID_NOT_GRANTED: Self = Self(0x20);
+    /// The executable is set-GID, but executing it did not change the effective GID.
+    ///
+    /// This is only reported by [`ungranted_privileges()`].
+    ///
+    /// [`ungranted_privileges()`]: ./fn.ungranted_privileges.html
+    pub const SETGID_NOT_GRANTED: Self = Self(0x40);
+
+    const ALL_BITS: u8 = 0x7f;
+
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::SETUID, "SETUID"),
+        (Self::SETGID, "SETGID"),
+        (Self::FILE_CAPS, "FILE_CAPS"),
+        (Self::LSM_TRANSITION, "LSM_TRANSITION"),
+        (Self::UNKNOWN, "UNKNOWN"),
+        (Self::SETUID_NOT_GRANTED, "SETUID_NOT_GRANTED"),
+        (Self::SETGID_NOT_GRANTED, "SETGID_NOT_GRANTED"),
+    ];
+
+    /// Returns a set with no flags set.
+    #[inline]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns the raw bits of this set.
+    #[inline]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Create a set from raw bits, discarding any bits that do not correspond to a flag.
+    #[inline]
+    pub const fn from_bits_truncate(bits: u8) -> Self {
+        Self(bits & Self::ALL_BITS)
+    }
+
+    /// Returns `true` if no flags are set (for [`secure_reason()`], this means the process does
+    /// not require secure execution).
+    ///
+    /// [`secure_reason()`]: ./fn.secure_reason.html
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all of the flags in `other` are set in `self`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any of the flags in `other` are set in `self`.
+    #[inline]
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for SecureReason {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for SecureReason {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for SecureReason {
+    type Output = Self;
+
+    #[inline]
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for SecureReason {
+    #[inline]
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl fmt::Debug for SecureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("(empty)");
+        }
+
+        let mut first = true;
+        for &(flag, name) in Self::NAMES.iter() {
+            if self.contains(flag) {
+                if !first {
+                    f.write_str(" | ")?;
+                }
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if the process currently holds permitted capabilities while running with a
+/// non-root effective UID (which indicates that they came from file capabilities).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn has_file_caps() -> bool {
+    if unsafe { libc::geteuid() } == 0 {
+        return false;
+    }
+
+    match crate::capabilities::capget() {
+        Ok((permitted, _, _)) => !permitted.is_empty(),
+        Err(_) => false,
+    }
+}
+
+/// Identical to [`secure_reason()`], but with no caching (i.e. probes the OS directly).
+///
+/// See the warnings in [`is_secure()`]'s documentation regarding the result changing; they apply
+/// even more strongly here, since this function also compares the process's current credentials.
+///
+/// [`secure_reason()`]: ./fn.secure_reason.html
+/// [`is_secure()`]: ./fn.is_secure.html
+pub fn secure_reason_uncached() -> SecureReason {
+    if !crate::is_secure_uncached() {
+        return SecureReason::empty();
+    }
+
+    let mut reason = SecureReason::empty();
+
+    unsafe {
+        if libc::geteuid() != libc::getuid() {
+            reason |= SecureReason::SETUID;
+        }
+        if libc::getegid() != libc::getgid() {
+            reason |= SecureReason::SETGID;
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if has_file_caps() {
+        reason |= SecureReason::FILE_CAPS;
+    }
+
+    if reason.is_empty() {
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            reason = SecureReason::LSM_TRANSITION;
+        } else {
+            reason = SecureReason::UNKNOWN;
+        }
+    }
+
+    reason
+}
+
+/// Determine why this program was launched in a way that requires "secure execution".
+///
+/// If [`is_secure_uncached()`] returns `false`, this returns an empty set. Otherwise, it compares
+/// the process's real and effective UIDs/GIDs and (on Linux) checks for capabilities granted by
+/// file capabilities. If none of those explain the result, then on Linux
+/// [`SecureReason::LSM_TRANSITION`] is reported; on other platforms, [`SecureReason::UNKNOWN`]
+/// is reported.
+///
+/// The result is cached after the first call, using the same scheme as [`is_secure()`]. All of
+/// the warnings in [`is_secure()`]'s documentation (most importantly, that it should be called
+/// early, before changing UIDs/GIDs) apply here too.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`is_secure_uncached()`]: ./fn.is_secure_uncached.html
+/// [`SecureReason::LSM_TRANSITION`]: ./struct.SecureReason.html#associatedconstant.LSM_TRANSITION
+/// [`SecureReason::UNKNOWN`]: ./struct.SecureReason.html#associatedconstant.UNKNOWN
+pub fn secure_reason() -> SecureReason {
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    // The high bit is never set in a valid SecureReason, so it marks "not yet determined".
+    static RES: AtomicU8 = AtomicU8::new(0x80);
+
+    match RES.load(Ordering::SeqCst) {
+        0x80 => {
+            let res = secure_reason_uncached();
+            RES.store(res.bits(), Ordering::SeqCst);
+            res
+        }
+
+        bits => SecureReason::from_bits_truncate(bits),
+    }
+}
+
+/// Returns `true` if the `no_new_privs` flag is set for the current process.
+///
+/// When this flag is set (on Linux, with `prctl(PR_SET_NO_NEW_PRIVS)`; on FreeBSD, with
+/// `procctl(PROC_NO_NEW_PRIVS_CTL)`), executing a set-UID/set-GID binary does not change the
+/// process's UIDs/GIDs, so such a binary runs unprivileged (and [`is_secure()`] may return
+/// `false`). The flag is inherited by child processes and cannot be cleared.
+///
+/// On other platforms, this always returns `false`.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+#[allow(clippy::needless_return)]
+pub fn no_new_privs() -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            return unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) } == 1;
+        } else if #[cfg(target_os = "freebsd")] {
+            let mut status: libc::c_int = 0;
+            return unsafe {
+                libc::procctl(
+                    libc::P_PID,
+                    0,
+                    libc::PROC_NO_NEW_PRIVS_STATUS,
+                    &mut status as *mut _ as *mut libc::c_void,
+                )
+            } == 0
+                && status == libc::PROC_NO_NEW_PRIVS_ENABLE;
+        } else {
+            return false;
+        }
+    }
+}
+
+/// Check whether the current executable is installed set-UID/set-GID, but executing it did not
+/// actually grant the corresponding privileges.
+///
+/// This happens if (among other things) the `no_new_privs` flag is set (see [`no_new_privs()`]),
+/// the executable is on a filesystem mounted `nosuid`, or the process was being traced when it
+/// was executed. In those cases, the program would usually fail later with a confusing
+/// "permission denied" error; this allows it to report the real problem instead.
+///
+/// This `stat()`s the executable (through `/proc/self/exe` on Linux, or `/proc/curproc/file` on
+/// FreeBSD if procfs is mounted), and compares its owner and set-UID/set-GID bits against the
+/// effective UID/GID from [`initial_credentials()`]. It returns a set containing
+/// [`SecureReason::SETUID_NOT_GRANTED`] and/or [`SecureReason::SETGID_NOT_GRANTED`], or an empty
+/// set if the privileges were granted (or the executable is not set-UID/set-GID, or it could not
+/// be checked).
+///
+/// [`no_new_privs()`]: ./fn.no_new_privs.html
+/// [`initial_credentials()`]: ./fn.initial_credentials.html
+/// [`SecureReason::SETUID_NOT_GRANTED`]: ./struct.SecureReason.html#associatedconstant.SETUID_NOT_GRANTED
+/// [`SecureReason::SETGID_NOT_GRANTED`]: ./struct.SecureReason.html#associatedconstant.SETGID_NOT_GRANTED
+pub fn ungranted_privileges() -> SecureReason {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let exe: &[u8] = b"/proc/self/exe\0";
+        } else if #[cfg(target_os = "freebsd")] {
+            let exe: &[u8] = b"/proc/curproc/file\0";
+        } else {
+            return SecureReason::empty();
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    ungranted_privileges_for(exe)
+}
+
+/// Check the file at `path` (which must be NUL-terminated) as described in
+/// [`ungranted_privileges()`].
+///
+/// [`ungranted_privileges()`]: ./fn.ungranted_privileges.html
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+// `st_mode` and `mode_t` are not the same type on every platform
+#[allow(clippy::unnecessary_cast)]
+fn ungranted_privileges_for(path: &[u8]) -> SecureReason {
+    debug_assert_eq!(path.last(), Some(&0));
+
+    let mut st = unsafe { core::mem::zeroed::<libc::stat>() };
+    if unsafe { libc::stat(path.as_ptr() as *const libc::c_char, &mut st) } != 0 {
+        return SecureReason::empty();
+    }
+
+    let creds = crate::initial_credentials();
+    let mut reason = SecureReason::empty();
+
+    if st.st_mode as u32 & libc::S_ISUID as u32 != 0 && st.st_uid != creds.euid() {
+        reason |= SecureReason::SETUID_NOT_GRANTED;
+    }
+    if st.st_mode as u32 & libc::S_ISGID as u32 != 0 && st.st_gid != creds.egid() {
+        reason |= SecureReason::SETGID_NOT_GRANTED;
+    }
+
+    reason
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_reason() {
+        assert!(secure_reason_uncached().is_empty());
+        assert!(secure_reason().is_empty());
+        assert!(secure_reason().is_empty());
+    }
+
+    #[test]
+    fn test_secure_reason_flags() {
+        let reason = SecureReason::SETUID | SecureReason::FILE_CAPS;
+
+        assert!(reason.contains(SecureReason::SETUID));
+        assert!(!reason.contains(SecureReason::SETUID | SecureReason::SETGID));
+        assert!(reason.intersects(SecureReason::SETUID | SecureReason::SETGID));
+        assert_eq!(reason & SecureReason::FILE_CAPS, SecureReason::FILE_CAPS);
+        assert_eq!(SecureReason::from_bits_truncate(0xff).bits(), 0x7f);
+    }
+
+    #[test]
+    fn test_no_new_privs() {
+        assert!(ungranted_privileges().is_empty());
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        assert!(crate::util::run_in_child(|| {
+            unsafe {
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+            }
+            no_new_privs()
+        }));
+    }
+
+    #[cfg(all(
+        feature = "std",
+        any(target_os = "linux", target_os = "android", target_os = "freebsd")
+    ))]
+    #[test]
+    fn test_ungranted_privileges() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::PermissionsExt;
+
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let dir = crate::util::TempDir::new("ungranted");
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o6755)).unwrap();
+
+        let mut cpath = path.as_os_str().as_bytes().to_vec();
+        cpath.push(0);
+
+        let unowned =
+            unsafe { libc::chown(cpath.as_ptr() as *const libc::c_char, 65534, 65534) } == 0;
+        // chown() clears the set-UID/set-GID bits
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o6755)).unwrap();
+        let reason = ungranted_privileges_for(&cpath);
+
+        if unowned {
+            assert_eq!(
+                reason,
+                SecureReason::SETUID_NOT_GRANTED | SecureReason::SETGID_NOT_GRANTED
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_secure_reason_debug() {
+        let reason = SecureReason::SETUID | SecureReason::FILE_CAPS;
+
+        assert_eq!(format!("{:?}", reason), "SETUID | FILE_CAPS");
+        assert_eq!(format!("{:?}", SecureReason::empty()), "(empty)");
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{Apply, Errno};
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", target_env = "gnu"))] {
+        pub(crate) type Resource = libc::__rlimit_resource_t;
+    } else {
+        pub(crate) type Resource = libc::c_int;
+    }
+}
+
+/// The minimum soft limit for `RLIMIT_STACK` in the default [`RlimitPolicy`] (8 MiB).
+///
+/// [`RlimitPolicy`]: ./struct.RlimitPolicy.html
+pub const DEFAULT_MIN_STACK: libc::rlim_t = 8 * 1024 * 1024;
+
+/// A resource limit, as returned by `getrlimit()`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Rlimit {
+    /// The soft limit (`rlim_cur`).
+    pub soft: libc::rlim_t,
+    /// The hard limit (`rlim_max`).
+    pub hard: libc::rlim_t,
+}
+
+impl Rlimit {
+    pub(crate) fn get(resource: Resource) -> Result<Self, Errno> {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut rlim) } != 0 {
+            return Err(Errno::last());
+        }
+
+        Ok(Self {
+            soft: rlim.rlim_cur,
+            hard: rlim.rlim_max,
+        })
+    }
+
+    fn set(self, resource: Resource) -> Result<(), Errno> {
+        let rlim = libc::rlimit {
+            rlim_cur: self.soft,
+            rlim_max: self.hard,
+        };
+        if unsafe { libc::setrlimit(resource, &rlim) } != 0 {
+            return Err(Errno::last());
+        }
+        Ok(())
+    }
+}
+
+/// Controls which resource limits [`sanitize_rlimits()`] adjusts.
+///
+/// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RlimitPolicy {
+    /// When the limits should be adjusted. (Default: [`Apply::IfSecure`].)
+    ///
+    /// [`Apply::IfSecure`]: ./enum.Apply.html#variant.IfSecure
+    pub apply: Apply,
+    /// Raise the soft limit for `RLIMIT_FSIZE` (the maximum size of a file the process can write)
+    /// to the hard limit. (Default: `true`.)
+    pub raise_fsize: bool,
+    /// Raise the soft limit for `RLIMIT_NOFILE` (the maximum number of open file descriptors) to
+    /// the hard limit. (Default: `true`.)
+    pub raise_nofile: bool,
+    /// Raise the soft limit for `RLIMIT_STACK` to at least this value (or the hard limit, if that
+    /// is lower). (Default: `Some(DEFAULT_MIN_STACK)`.)
+    pub min_stack: Option<libc::rlim_t>,
+    /// Set both limits for `RLIMIT_CORE` to 0, disabling core dumps. (Default: `true`.)
+    pub disable_core: bool,
+}
+
+impl Default for RlimitPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            apply: Apply::IfSecure,
+            raise_fsize: true,
+            raise_nofile: true,
+            min_stack: Some(DEFAULT_MIN_STACK),
+            disable_core: true,
+        }
+    }
+}
+
+/// A report of the resource limits inspected by [`sanitize_rlimits()`].
+///
+/// Each method returns the original value of the corresponding limit, or `None` if the policy
+/// did not cover it (or the function did nothing).
+///
+/// [`sanitize_rlimits()`]: ./fn.sanitize_rlimits.html
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RlimitReport {
+    fsize: Option<Rlimit>,
+    nofile: Option<Rlimit>,
+    stack: Option<Rlimit>,
+    core: Option<Rlimit>,
+}
+
+impl RlimitReport {
+    /// Get the original value of `RLIMIT_FSIZE`.
+    #[inline]
+    pub fn fsize(&self) -> Option<Rlimit> {
+        self.fsize
+    }
+
+    /// Get the original value of `RLIMIT_NOFILE`.
+    #[inline]
+    pub fn nofile(&self) -> Option<Rlimit> {
+        self.nofile
+    }
+
+    /// Get the original value of `RLIMIT_STACK`.
+    #[inline]
+    pub fn stack(&self) -> Option<Rlimit> {
+        self.stack
+    }
+
+    /// Get the original value of `RLIMIT_CORE`.
+    #[inline]
+    pub fn core(&self) -> Option<Rlimit> {
+        self.core
+    }
+
+    /// Returns `true` if no limits were inspected.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fsize.is_none() && self.nofile.is_none() && self.stack.is_none() && self.core.is_none()
+    }
+}
+
+/// Reset resource limits inherited from the process that executed this one to safe values.
+///
+/// Resource limits are preserved across `execve()`, so the invoker of a set-UID program could (for
+/// example) set a low `RLIMIT_FSIZE` or `RLIMIT_NOFILE` to make it fail halfway through updating a
+/// file, or raise `RLIMIT_CORE` to make it dump core (possibly with secrets in memory) if it
+/// crashes.
+///
+/// If `policy.apply` says this should take effect, this adjusts the limits as specified by
+/// `policy` (by default: raising the soft limits for `RLIMIT_FSIZE` and `RLIMIT_NOFILE` to the
+/// hard limits, raising the soft limit for `RLIMIT_STACK` to at least [`DEFAULT_MIN_STACK`], and
+/// setting `RLIMIT_CORE` to 0). Otherwise, it does nothing and returns an empty report.
+///
+/// Hard limits cannot be raised without privileges, so only the soft limits are raised. Note that
+/// once the hard limit for `RLIMIT_CORE` has been lowered to 0, the process cannot raise it again
+/// (unless it has `CAP_SYS_RESOURCE` on Linux).
+///
+/// [`DEFAULT_MIN_STACK`]: ./constant.DEFAULT_MIN_STACK.html
+pub fn sanitize_rlimits(policy: &RlimitPolicy) -> Result<RlimitReport, Errno> {
+    let mut report = RlimitReport::default();
+
+    if !policy.apply.should_apply() {
+        return Ok(report);
+    }
+
+    if policy.raise_fsize {
+        let orig = Rlimit::get(libc::RLIMIT_FSIZE as Resource)?;
+        if orig.soft != orig.hard {
+            Rlimit {
+                soft: orig.hard,
+                hard: orig.hard,
+            }
+            .set(libc::RLIMIT_FSIZE as Resource)?;
+        }
+        report.fsize = Some(orig);
+    }
+
+    if policy.raise_nofile {
+        let orig = Rlimit::get(libc::RLIMIT_NOFILE as Resource)?;
+        if orig.soft != orig.hard {
+            raise_nofile(orig)?;
+        }
+        report.nofile = Some(orig);
+    }
+
+    if let Some(min_stack) = policy.min_stack {
+        let orig = Rlimit::get(libc::RLIMIT_STACK as Resource)?;
+        let min_stack = min_stack.min(orig.hard);
+        if orig.soft != libc::RLIM_INFINITY && orig.soft < min_stack {
+            Rlimit {
+                soft: min_stack,
+                hard: orig.hard,
+            }
+            .set(libc::RLIMIT_STACK as Resource)?;
+        }
+        report.stack = Some(orig);
+    }
+
+    if policy.disable_core {
+        report.core = Some(disable_core_dumps()?);
+    }
+
+    Ok(report)
+}
+
+/// The highest soft limit macOS accepts for `RLIMIT_NOFILE`.
+#[cfg(target_os = "macos")]
+const OPEN_MAX: libc::rlim_t = 10240;
+
+fn raise_nofile(orig: Rlimit) -> Result<(), Errno> {
+    let res = Rlimit {
+        soft: orig.hard,
+        hard: orig.hard,
+    }
+    .set(libc::RLIMIT_NOFILE as Resource);
+
+    // macOS refuses to set the soft limit above OPEN_MAX
+    #[cfg(target_os = "macos")]
+    if res == Err(Errno::from_raw(libc::EINVAL)) {
+        if orig.soft < OPEN_MAX {
+            return Rlimit {
+                soft: OPEN_MAX.min(orig.hard),
+                hard: orig.hard,
+            }
+            .set(libc::RLIMIT_NOFILE as Resource);
+        }
+        return Ok(());
+    }
+
+    res
+}
+
+/// Set both limits for `RLIMIT_CORE` to 0. Returns the original value.
+pub(crate) fn disable_core_dumps() -> Result<Rlimit, Errno> {
+    let orig = Rlimit::get(libc::RLIMIT_CORE as Resource)?;
+    if orig.soft != 0 || orig.hard != 0 {
+        Rlimit { soft: 0, hard: 0 }.set(libc::RLIMIT_CORE as Resource)?;
+    }
+    Ok(orig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_sanitize_rlimits() {
+        assert!(sanitize_rlimits(&RlimitPolicy::default())
+            .unwrap()
+            .is_empty());
+
+        assert!(run_in_child(|| {
+            let fsize = Rlimit::get(libc::RLIMIT_FSIZE as Resource).unwrap();
+            let nofile = Rlimit::get(libc::RLIMIT_NOFILE as Resource).unwrap();
+            let lowered_fsize = Rlimit {
+                soft: 4096.min(fsize.hard),
+                hard: fsize.hard,
+            };
+            let lowered_nofile = Rlimit {
+                soft: 16.min(nofile.hard),
+                hard: nofile.hard,
+            };
+            lowered_fsize.set(libc::RLIMIT_FSIZE as Resource).unwrap();
+            lowered_nofile.set(libc::RLIMIT_NOFILE as Resource).unwrap();
+
+            let report = sanitize_rlimits(&RlimitPolicy {
+                apply: Apply::Always,
+                ..Default::default()
+            })
+            .unwrap();
+
+            let stack = Rlimit::get(libc::RLIMIT_STACK as Resource).unwrap();
+
+            #[cfg(target_os = "macos")]
+            let nofile_soft = OPEN_MAX.min(nofile.hard);
+            #[cfg(not(target_os = "macos"))]
+            let nofile_soft = nofile.hard;
+
+            report.fsize() == Some(lowered_fsize)
+                && report.nofile() == Some(lowered_nofile)
+                && report.stack().is_some()
+                && report.core().is_some()
+                && Rlimit::get(libc::RLIMIT_FSIZE as Resource).unwrap().soft == fsize.hard
+                && Rlimit::get(libc::RLIMIT_NOFILE as Resource).unwrap().soft == nofile_soft
+                && (stack.soft == libc::RLIM_INFINITY
+                    || stack.soft >= DEFAULT_MIN_STACK.min(stack.hard))
+                && Rlimit::get(libc::RLIMIT_CORE as Resource).unwrap()
+                    == Rlimit { soft: 0, hard: 0 }
+        }));
+    }
+}
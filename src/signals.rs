@@ -0,0 +1,153 @@
+use crate::{Apply, Errno};
+
+/// The highest signal number that is checked (the highest real-time signal on Linux).
+const MAX_SIGNAL: libc::c_int = 64;
+
+/// A report of the changes made by [`reset_signal_state()`].
+///
+/// [`reset_signal_state()`]: ./fn.reset_signal_state.html
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SignalReport {
+    blocked: u64,
+    reset: u64,
+}
+
+impl SignalReport {
+    #[inline]
+    fn bit(sig: libc::c_int) -> u64 {
+        if (1..=MAX_SIGNAL).contains(&sig) {
+            1 << (sig - 1)
+        } else {
+            0
+        }
+    }
+
+    /// Returns `true` if the given signal was blocked (and has been unblocked).
+    #[inline]
+    pub fn was_blocked(&self, sig: libc::c_int) -> bool {
+        self.blocked & Self::bit(sig) != 0
+    }
+
+    /// Returns `true` if the given signal was ignored (and has been reset to the default
+    /// disposition).
+    #[inline]
+    pub fn was_reset(&self, sig: libc::c_int) -> bool {
+        self.reset & Self::bit(sig) != 0
+    }
+
+    /// Returns `true` if no changes were made.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.blocked == 0 && self.reset == 0
+    }
+}
+
+/// Reset the signal mask and any ignored signals inherited from the process that executed this
+/// one.
+///
+/// Both the signal mask and the set of ignored signals are preserved across `execve()`, so the
+/// invoker of a set-UID program can (for example) block `SIGTERM` or ignore `SIGPIPE` to alter the
+/// program's behavior.
+///
+/// If `apply` says this should take effect, this unblocks every signal in the calling thread's
+/// signal mask, and resets every signal that is currently ignored (other than those in
+/// `keep_ignored`) to its default disposition. Otherwise, it does nothing.
+///
+/// Note that the Rust standard library ignores `SIGPIPE` at startup (so that writes to closed
+/// pipes return `EPIPE` instead of killing the process). Include `SIGPIPE` in `keep_ignored` to
+/// preserve that behavior.
+///
+/// Handlers installed by the program itself are left alone, and alternate signal stacks are not
+/// inherited across `execve()`, so there is no need to reset them. This function should be called
+/// at the start of `main()`, before spawning any other threads (since it only affects the signal
+/// mask of the calling thread).
+pub fn reset_signal_state(
+    keep_ignored: &[libc::c_int],
+    apply: Apply,
+) -> Result<SignalReport, Errno> {
+    let mut report = SignalReport::default();
+
+    if !apply.should_apply() {
+        return Ok(report);
+    }
+
+    unsafe {
+        let mut empty = core::mem::zeroed::<libc::sigset_t>();
+        let mut old = core::mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut empty);
+
+        let ret = libc::pthread_sigmask(libc::SIG_SETMASK, &empty, &mut old);
+        if ret != 0 {
+            return Err(Errno::from_raw(ret));
+        }
+
+        for sig in 1..=MAX_SIGNAL {
+            if libc::sigismember(&old, sig) == 1 {
+                report.blocked |= SignalReport::bit(sig);
+            }
+        }
+
+        for sig in 1..=MAX_SIGNAL {
+            if sig == libc::SIGKILL || sig == libc::SIGSTOP || keep_ignored.contains(&sig) {
+                continue;
+            }
+
+            let mut action = core::mem::zeroed::<libc::sigaction>();
+            // This fails for invalid signals (and, with glibc, for the signals it reserves for
+            // internal use)
+            if libc::sigaction(sig, core::ptr::null(), &mut action) != 0
+                || action.sa_sigaction != libc::SIG_IGN
+            {
+                continue;
+            }
+
+            let mut action = core::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(sig, &action, core::ptr::null_mut()) != 0 {
+                return Err(Errno::last());
+            }
+            report.reset |= SignalReport::bit(sig);
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_reset_signal_state() {
+        assert!(reset_signal_state(&[], Apply::IfSecure).unwrap().is_empty());
+
+        assert!(run_in_child(|| unsafe {
+            let mut mask = core::mem::zeroed::<libc::sigset_t>();
+            libc::sigemptyset(&mut mask);
+            libc::sigaddset(&mut mask, libc::SIGUSR1);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &mask, core::ptr::null_mut());
+
+            libc::signal(libc::SIGUSR2, libc::SIG_IGN);
+            libc::signal(libc::SIGTERM, libc::SIG_IGN);
+
+            let report = reset_signal_state(&[libc::SIGTERM], Apply::Always).unwrap();
+
+            let mut action = core::mem::zeroed::<libc::sigaction>();
+            libc::pthread_sigmask(libc::SIG_BLOCK, core::ptr::null(), &mut mask);
+
+            report.was_blocked(libc::SIGUSR1)
+                && !report.was_blocked(libc::SIGUSR2)
+                && report.was_reset(libc::SIGUSR2)
+                && !report.was_reset(libc::SIGTERM)
+                && libc::sigismember(&mask, libc::SIGUSR1) == 0
+                && libc::sigaction(libc::SIGUSR2, core::ptr::null(), &mut action) == 0
+                && action.sa_sigaction == libc::SIG_DFL
+                && libc::sigaction(libc::SIGTERM, core::ptr::null(), &mut action) == 0
+                && action.sa_sigaction == libc::SIG_IGN
+        }));
+    }
+}
@@ -0,0 +1,145 @@
+/// Get the PID of the process that is tracing this one (for example, a debugger attached with
+/// `ptrace()`), or `None` if it is not being traced.
+///
+/// The information is obtained from:
+///
+/// - On Linux, the `TracerPid` field in `/proc/self/status`. (Strictly speaking, this is the
+///   thread ID of the tracing thread, which may differ from its process ID.)
+/// - On FreeBSD, the `P_TRACED` flag and the `ki_tracer` field from the `kern.proc.pid` sysctl.
+/// - On macOS, the `P_TRACED` flag from the `kern.proc.pid` sysctl. macOS does not report the
+///   tracer's PID, but attaching a debugger makes it the parent of the traced process, so this
+///   returns the parent PID.
+///
+/// On other platforms (or if the information cannot be retrieved), this always returns `None`.
+///
+/// Note that the result only reflects the moment it was checked; a debugger could attach
+/// immediately afterward. To prevent that, use [`harden_against_inspection()`].
+///
+/// [`harden_against_inspection()`]: ./fn.harden_against_inspection.html
+#[allow(clippy::needless_return)]
+pub fn tracer_pid() -> Option<libc::pid_t> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let mut buf = [0; 4096];
+            let len = crate::util::read_file(b"/proc/self/status\0", &mut buf)?;
+
+            for line in buf[..len].split(|&ch| ch == b'\n') {
+                if let Some(value) = line.strip_prefix(b"TracerPid:") {
+                    let pid = core::str::from_utf8(value).ok()?.trim().parse().ok()?;
+                    return if pid != 0 { Some(pid) } else { None };
+                }
+            }
+
+            return None;
+        } else if #[cfg(target_os = "freebsd")] {
+            let mut mib = [
+                libc::CTL_KERN,
+                libc::KERN_PROC,
+                libc::KERN_PROC_PID,
+                unsafe { libc::getpid() },
+            ];
+            let mut info = unsafe { core::mem::zeroed::<libc::kinfo_proc>() };
+            let mut len = core::mem::size_of::<libc::kinfo_proc>();
+
+            if unsafe {
+                libc::sysctl(
+                    mib.as_mut_ptr(),
+                    mib.len() as libc::c_uint,
+                    &mut info as *mut _ as *mut libc::c_void,
+                    &mut len,
+                    core::ptr::null_mut(),
+                    0,
+                )
+            } != 0
+                || len != core::mem::size_of::<libc::kinfo_proc>()
+            {
+                return None;
+            }
+
+            return if info.ki_flag & libc::P_TRACED as libc::c_long != 0 && info.ki_tracer > 0 {
+                Some(info.ki_tracer)
+            } else {
+                None
+            };
+        } else if #[cfg(target_os = "macos")] {
+            // libc does not define `struct kinfo_proc` for macOS, so read `kp_proc.p_flag`
+            // directly. It follows a two-pointer union and two more pointers.
+            const P_FLAG_OFFSET: usize = 4 * core::mem::size_of::<*const libc::c_void>();
+            const P_TRACED: libc::c_int = 0x800;
+
+            let mut mib = [
+                libc::CTL_KERN,
+                libc::KERN_PROC,
+                libc::KERN_PROC_PID,
+                unsafe { libc::getpid() },
+            ];
+            // sizeof(struct kinfo_proc) is 648 on 64-bit systems
+            let mut buf = [0u64; 128];
+            let mut len = core::mem::size_of_val(&buf);
+
+            if unsafe {
+                libc::sysctl(
+                    mib.as_mut_ptr(),
+                    mib.len() as libc::c_uint,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    &mut len,
+                    core::ptr::null_mut(),
+                    0,
+                )
+            } != 0
+                || len < P_FLAG_OFFSET + core::mem::size_of::<libc::c_int>()
+            {
+                return None;
+            }
+
+            let p_flag = unsafe {
+                core::ptr::read((buf.as_ptr() as *const u8).add(P_FLAG_OFFSET) as *const libc::c_int)
+            };
+
+            return if p_flag & P_TRACED != 0 {
+                Some(unsafe { libc::getppid() })
+            } else {
+                None
+            };
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Returns `true` if this process is being traced (for example, by a debugger).
+///
+/// See [`tracer_pid()`] for details.
+///
+/// [`tracer_pid()`]: ./fn.tracer_pid.html
+#[inline]
+pub fn is_traced() -> bool {
+    tracer_pid().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracer_pid() {
+        // The tests may be run under a debugger, so only check consistency
+        assert_eq!(is_traced(), tracer_pid().is_some());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_tracer_pid_traceme() {
+        // The tracer is the thread that forks the child
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+
+        assert!(crate::util::run_in_child(|| {
+            // ptrace() may be restricted (for example, by seccomp in containers)
+            if unsafe { libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0) } != 0 {
+                return true;
+            }
+
+            tracer_pid() == Some(tid) && is_traced()
+        }));
+    }
+}
@@ -0,0 +1,353 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::Errno;
+
+/// The maximum number of symbolic links followed while resolving a path (the same limit Linux
+/// uses).
+const MAX_SYMLINKS: usize = 40;
+
+/// The users and groups trusted by [`verify_trusted_path()`].
+///
+/// `root` (UID 0) is always trusted. Additional users and groups can be trusted with
+/// [`trust_uid()`] and [`trust_gid()`]:
+///
+/// ```
+/// let policy = secure_exec::TrustPolicy::new()
+///     .trust_uid(unsafe { libc::geteuid() })
+///     .allow_sticky(true);
+/// ```
+///
+/// [`verify_trusted_path()`]: ./fn.verify_trusted_path.html
+/// [`trust_uid()`]: #method.trust_uid
+/// [`trust_gid()`]: #method.trust_gid
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TrustPolicy {
+    uids: Vec<libc::uid_t>,
+    gids: Vec<libc::gid_t>,
+    allow_sticky: bool,
+}
+
+impl TrustPolicy {
+    /// Create a new policy that only trusts `root`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust files and directories owned by the given user.
+    pub fn trust_uid(mut self, uid: libc::uid_t) -> Self {
+        self.uids.push(uid);
+        self
+    }
+
+    /// Allow files and directories that are writable by the given group.
+    pub fn trust_gid(mut self, gid: libc::gid_t) -> Self {
+        self.gids.push(gid);
+        self
+    }
+
+    /// Allow directories that are group- or world-writable if they have the sticky bit set (like
+    /// `/tmp`). (Default: `false`.)
+    ///
+    /// In such directories, users can only rename or remove their own files, so anything
+    /// underneath them is still safe as long as it is owned by a trusted user (which is checked
+    /// separately).
+    pub fn allow_sticky(mut self, allow: bool) -> Self {
+        self.allow_sticky = allow;
+        self
+    }
+
+    fn check_owner(&self, uid: libc::uid_t) -> Result<(), TrustErrorKind> {
+        if uid == 0 || self.uids.contains(&uid) {
+            Ok(())
+        } else {
+            Err(TrustErrorKind::UntrustedOwner(uid))
+        }
+    }
+
+    /// Check the owner and permissions of a file or directory (which is not a symbolic link).
+    // `mode_t` is not `u32` on every platform
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn check(
+        &self,
+        uid: libc::uid_t,
+        gid: libc::gid_t,
+        mode: u32,
+    ) -> Result<(), TrustErrorKind> {
+        self.check_owner(uid)?;
+
+        let sticky_dir =
+            mode & libc::S_IFMT as u32 == libc::S_IFDIR as u32 && mode & libc::S_ISVTX as u32 != 0;
+        if sticky_dir && self.allow_sticky {
+            return Ok(());
+        }
+
+        if mode & libc::S_IWOTH as u32 != 0 {
+            Err(TrustErrorKind::WorldWritable)
+        } else if mode & libc::S_IWGRP as u32 != 0 && !self.gids.contains(&gid) {
+            Err(TrustErrorKind::GroupWritable(gid))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The reason a path component failed verification. See [`TrustError`].
+///
+/// [`TrustError`]: ./struct.TrustError.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TrustErrorKind {
+    /// The component could not be inspected.
+    Os(Errno),
+    /// The component is owned by an untrusted user.
+    UntrustedOwner(libc::uid_t),
+    /// The component is writable by an untrusted group.
+    GroupWritable(libc::gid_t),
+    /// The component is writable by all users.
+    WorldWritable,
+    /// Too many symbolic links were encountered while resolving the path.
+    TooManySymlinks,
+    /// The path contains a `..` component, which [`SecureOpenOptions`] does not allow.
+    ///
+    /// [`SecureOpenOptions`]: ./struct.SecureOpenOptions.html
+    ParentDir,
+    /// The component is on a different filesystem than its parent, which is not allowed by
+    /// [`SecureOpenOptions::same_filesystem()`].
+    ///
+    /// [`SecureOpenOptions::same_filesystem()`]: ./struct.SecureOpenOptions.html#method.same_filesystem
+    CrossesFilesystem,
+}
+
+impl fmt::Display for TrustErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Os(errno) => errno.fmt(f),
+            Self::UntrustedOwner(uid) => write!(f, "owned by untrusted user {}", uid),
+            Self::GroupWritable(gid) => write!(f, "writable by untrusted group {}", gid),
+            Self::WorldWritable => f.write_str("writable by all users"),
+            Self::TooManySymlinks => f.write_str("too many levels of symbolic links"),
+            Self::ParentDir => f.write_str("'..' components are not allowed"),
+            Self::CrossesFilesystem => f.write_str("crosses into a different filesystem"),
+        }
+    }
+}
+
+/// An error returned by [`verify_trusted_path()`], naming the path component that failed
+/// verification.
+///
+/// [`verify_trusted_path()`]: ./fn.verify_trusted_path.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrustError {
+    path: PathBuf,
+    kind: TrustErrorKind,
+}
+
+impl TrustError {
+    pub(crate) fn new(path: PathBuf, kind: TrustErrorKind) -> Self {
+        Self { path, kind }
+    }
+
+    /// Get the (resolved) path of the component that failed verification.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the reason the component failed verification.
+    #[inline]
+    pub fn kind(&self) -> TrustErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for TrustError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+impl std::error::Error for TrustError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.kind {
+            TrustErrorKind::Os(ref errno) => Some(errno),
+            _ => None,
+        }
+    }
+}
+
+impl From<TrustError> for std::io::Error {
+    fn from(err: TrustError) -> Self {
+        match err.kind {
+            TrustErrorKind::Os(errno) => errno.into(),
+            _ => Self::new(std::io::ErrorKind::PermissionDenied, err),
+        }
+    }
+}
+
+#[inline]
+fn io_errno(err: std::io::Error) -> TrustErrorKind {
+    TrustErrorKind::Os(Errno::from_raw(err.raw_os_error().unwrap_or(libc::EIO)))
+}
+
+/// Push the components of `path` onto `stack` so that the first component is on top.
+fn push_components(stack: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => stack.push(name.to_os_string()),
+            Component::ParentDir => stack.push("..".into()),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => (),
+        }
+    }
+}
+
+/// Verify that no untrusted user could have tampered with the file or directory at `path`.
+///
+/// This resolves `path` one component at a time, starting from `/` (relative paths are resolved
+/// relative to the current directory, which is also verified). Every directory along the way, as
+/// well as the final file or directory, must:
+///
+/// - Be owned by `root` or a user trusted by `policy`.
+/// - Not be writable by any group other than one trusted by `policy`.
+/// - Not be world-writable.
+///
+/// The last two rules do not apply to directories with the sticky bit set if
+/// [`TrustPolicy::allow_sticky()`] is enabled.
+///
+/// Symbolic links are followed (up to a limit of 40), and the path they point to is verified in
+/// the same way. Each symbolic link must also be owned by a trusted user.
+///
+/// On failure, the returned error names the (resolved) path of the offending component.
+///
+/// Unlike most checks on the filesystem, a successful result cannot be invalidated by an untrusted
+/// user afterward, since no untrusted user can modify any of the components.
+///
+/// [`TrustPolicy::allow_sticky()`]: ./struct.TrustPolicy.html#method.allow_sticky
+pub fn verify_trusted_path<P: AsRef<Path>>(
+    path: P,
+    policy: &TrustPolicy,
+) -> Result<(), TrustError> {
+    let path = path.as_ref();
+    let mut current = PathBuf::from("/");
+
+    let mut stack = Vec::new();
+    push_components(&mut stack, path);
+    if path.is_relative() {
+        let cwd = std::env::current_dir().map_err(|e| TrustError::new(".".into(), io_errno(e)))?;
+        push_components(&mut stack, &cwd);
+    }
+
+    let meta = std::fs::symlink_metadata(&current)
+        .map_err(|e| TrustError::new(current.clone(), io_errno(e)))?;
+    policy
+        .check(meta.uid(), meta.gid(), meta.mode())
+        .map_err(|kind| TrustError::new(current.clone(), kind))?;
+
+    let mut links = 0;
+
+    while let Some(name) = stack.pop() {
+        if name == ".." {
+            // `current` has no symbolic links in it, so this is accurate
+            current.pop();
+            continue;
+        }
+
+        let next = current.join(&name);
+        let meta = std::fs::symlink_metadata(&next)
+            .map_err(|e| TrustError::new(next.clone(), io_errno(e)))?;
+
+        if meta.file_type().is_symlink() {
+            policy
+                .check_owner(meta.uid())
+                .map_err(|kind| TrustError::new(next.clone(), kind))?;
+
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(TrustError::new(next, TrustErrorKind::TooManySymlinks));
+            }
+
+            let target = std::fs::read_link(&next)
+                .map_err(|e| TrustError::new(next.clone(), io_errno(e)))?;
+            if target.is_absolute() {
+                current = PathBuf::from("/");
+            }
+            push_components(&mut stack, &target);
+            continue;
+        }
+
+        policy
+            .check(meta.uid(), meta.gid(), meta.mode())
+            .map_err(|kind| TrustError::new(next.clone(), kind))?;
+        current = next;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::util::TempDir;
+
+    fn set_mode(path: &Path, mode: u32) {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_verify_trusted_path() {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        let tmp = std::env::temp_dir();
+        let dir = TempDir::new("trust");
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        set_mode(&file, 0o644);
+        std::os::unix::fs::symlink(&file, dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("loop", dir.path().join("loop")).unwrap();
+
+        let policy = TrustPolicy::new().trust_uid(uid).allow_sticky(true);
+        let canon_dir = dir.path().canonicalize().unwrap();
+
+        if std::fs::metadata(&tmp).unwrap().mode() & 0o1000 != 0 {
+            assert!(verify_trusted_path(&file, &TrustPolicy::new().trust_uid(uid)).is_err());
+        }
+        if uid != 0 {
+            let err = verify_trusted_path(&file, &TrustPolicy::new().allow_sticky(true));
+            assert_eq!(err.unwrap_err().kind(), TrustErrorKind::UntrustedOwner(uid));
+        }
+
+        verify_trusted_path("/", &TrustPolicy::new()).unwrap();
+        verify_trusted_path(&file, &policy).unwrap();
+        verify_trusted_path(dir.path().join("link"), &policy).unwrap();
+        verify_trusted_path(
+            dir.path().join("../").join(dir.path().file_name().unwrap()),
+            &policy,
+        )
+        .unwrap();
+
+        let err = verify_trusted_path(dir.path().join("loop"), &policy).unwrap_err();
+        assert_eq!(err.kind(), TrustErrorKind::TooManySymlinks);
+        let err = verify_trusted_path(dir.path().join("missing"), &policy).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            TrustErrorKind::Os(Errno::from_raw(libc::ENOENT))
+        );
+
+        set_mode(&file, 0o666);
+        let err = verify_trusted_path(dir.path().join("link"), &policy).unwrap_err();
+        assert_eq!(err.path(), canon_dir.join("file"));
+        assert_eq!(err.kind(), TrustErrorKind::WorldWritable);
+
+        set_mode(&file, 0o644);
+        set_mode(dir.path(), 0o775);
+        let err = verify_trusted_path(&file, &policy).unwrap_err();
+        assert_eq!(err.path(), canon_dir);
+        assert_eq!(err.kind(), TrustErrorKind::GroupWritable(gid));
+        verify_trusted_path(&file, &policy.trust_gid(gid)).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::Apply;
+
+/// A safe default umask (`0o022`), which prevents files created by the process from being
+/// writable by anyone but their owner.
+///
+/// Programs that create files containing sensitive data may want to use `0o077` instead.
+pub const DEFAULT_UMASK: libc::mode_t = 0o022;
+
+/// Sentinel value indicating that the inherited umask has not been recorded yet
+const UNSET: u32 = u32::MAX;
+
+static INHERITED: AtomicU32 = AtomicU32::new(UNSET);
+
+/// Replace the umask inherited from the process that executed this one with a safe value.
+///
+/// The umask is preserved across `execve()`, so the invoker of a set-UID program could set it to
+/// 0 to make the files the program creates world-writable.
+///
+/// If `apply` says this should take effect, this sets the umask to `mask` (usually
+/// [`DEFAULT_UMASK`]). Otherwise, the umask is left unchanged. (However, since the only way to
+/// read the umask is to change it, the umask is briefly set to `mask` and then restored.)
+///
+/// Either way, the first call to this function records the inherited umask, which can then be
+/// retrieved with [`inherited_umask()`] (for example, to honor it when creating files on the
+/// user's behalf after dropping privileges). Returns the umask that was in effect before this
+/// call.
+///
+/// This should be called at the start of `main()`, before any other threads are spawned.
+///
+/// [`DEFAULT_UMASK`]: ./constant.DEFAULT_UMASK.html
+/// [`inherited_umask()`]: ./fn.inherited_umask.html
+pub fn sanitize_umask(mask: libc::mode_t, apply: Apply) -> libc::mode_t {
+    let old = unsafe { libc::umask(mask) };
+    if !apply.should_apply() {
+        unsafe {
+            libc::umask(old);
+        }
+    }
+
+    let _ = INHERITED.compare_exchange(UNSET, old as u32, Ordering::SeqCst, Ordering::SeqCst);
+
+    old
+}
+
+/// Get the umask that was recorded by the first call to [`sanitize_umask()`], or `None` if it has
+/// not been called yet.
+///
+/// [`sanitize_umask()`]: ./fn.sanitize_umask.html
+pub fn inherited_umask() -> Option<libc::mode_t> {
+    match INHERITED.load(Ordering::SeqCst) {
+        UNSET => None,
+        mask => Some(mask as libc::mode_t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::run_in_child;
+
+    #[test]
+    fn test_sanitize_umask() {
+        assert!(run_in_child(|| {
+            unsafe {
+                libc::umask(0);
+            }
+
+            let unchanged = inherited_umask().is_none()
+                && sanitize_umask(DEFAULT_UMASK, Apply::IfSecure) == 0
+                && unsafe { libc::umask(0) } == 0;
+
+            unchanged
+                && inherited_umask() == Some(0)
+                && sanitize_umask(DEFAULT_UMASK, Apply::Always) == 0
+                && sanitize_umask(0o077, Apply::Always) == DEFAULT_UMASK
+                && unsafe { libc::umask(0) } == 0o077
+                && inherited_umask() == Some(0)
+        }));
+    }
+}
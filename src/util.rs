@@ -0,0 +1,120 @@
+/// Read up to `buf.len()` bytes from the file at `path` (which must be NUL-terminated) into `buf`.
+///
+/// Returns the number of bytes read, or `None` if the file could not be opened or read.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn read_file(path: &[u8], buf: &mut [u8]) -> Option<usize> {
+    debug_assert_eq!(path.last(), Some(&0));
+
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr() as *const libc::c_char,
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return None;
+    }
+
+    let mut len = 0;
+    let res = loop {
+        if len == buf.len() {
+            break Some(len);
+        }
+
+        let n = unsafe {
+            libc::read(
+                fd,
+                buf[len..].as_mut_ptr() as *mut libc::c_void,
+                buf.len() - len,
+            )
+        };
+
+        if n > 0 {
+            len += n as usize;
+        } else if n == 0 {
+            break Some(len);
+        } else if errno() != libc::EINTR {
+            break None;
+        }
+    };
+
+    unsafe {
+        libc::close(fd);
+    }
+
+    res
+}
+
+/// Get the current thread's `errno` value.
+#[allow(clippy::needless_return)]
+pub(crate) fn errno() -> libc::c_int {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "dragonfly"))] {
+            return unsafe { *libc::__errno_location() };
+        } else if #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))] {
+            return unsafe { *libc::__errno() };
+        } else if #[cfg(any(target_os = "freebsd", target_os = "macos"))] {
+            return unsafe { *libc::__error() };
+        } else if #[cfg(any(target_os = "solaris", target_os = "illumos"))] {
+            return unsafe { *libc::___errno() };
+        } else if #[cfg(feature = "std")] {
+            return std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        } else {
+            return 0;
+        }
+    }
+}
+
+/// Run `f` in a forked child process and return whether it returned `true`.
+///
+/// This allows tests to perform process-wide changes (such as switching UIDs) without affecting
+/// the rest of the test suite.
+#[cfg(test)]
+pub(crate) fn run_in_child<F: FnOnce() -> bool>(f: F) -> bool {
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork() failed: {}", errno()),
+
+        0 => {
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+            unsafe { libc::_exit(if let Ok(true) = res { 0 } else { 1 }) }
+        }
+
+        pid => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+        }
+    }
+}
+
+/// A temporary directory for tests, which is removed when this is dropped.
+#[cfg(all(test, feature = "std"))]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(all(test, feature = "std"))]
+impl TempDir {
+    /// Create a directory named after `name` (and the current PID, so concurrent test runs don't
+    /// collide) under `std::env::temp_dir()`, with mode 0755.
+    pub(crate) fn new(name: &str) -> Self {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("secure_exec_{}_{}", name, std::process::id()));
+        std::fs::create_dir(&path).unwrap();
+        let dir = Self(path);
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
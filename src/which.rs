@@ -0,0 +1,150 @@
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::{verify_trusted_path, TrustPolicy};
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "android")] {
+        /// The search path used by [`secure_which()`] when the program requires "secure
+        /// execution".
+        ///
+        /// [`secure_which()`]: ./fn.secure_which.html
+        pub const SECURE_DEFAULT_PATH: &str = "/system/bin:/system/xbin:/vendor/bin";
+    } else if #[cfg(target_os = "macos")] {
+        /// The search path used by [`secure_which()`] when the program requires "secure
+        /// execution".
+        ///
+        /// [`secure_which()`]: ./fn.secure_which.html
+        pub const SECURE_DEFAULT_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin:/usr/local/bin";
+    } else if #[cfg(any(
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))] {
+        /// The search path used by [`secure_which()`] when the program requires "secure
+        /// execution".
+        ///
+        /// [`secure_which()`]: ./fn.secure_which.html
+        pub const SECURE_DEFAULT_PATH: &str =
+            "/sbin:/bin:/usr/sbin:/usr/bin:/usr/local/sbin:/usr/local/bin";
+    } else {
+        /// The search path used by [`secure_which()`] when the program requires "secure
+        /// execution".
+        ///
+        /// [`secure_which()`]: ./fn.secure_which.html
+        pub const SECURE_DEFAULT_PATH: &str =
+            "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+    }
+}
+
+/// Get the search path for executables.
+///
+/// If [`is_secure()`] returns `true`, this ignores the `PATH` environment variable and returns
+/// [`SECURE_DEFAULT_PATH`]. Otherwise, it returns the value of `PATH` (or
+/// [`SECURE_DEFAULT_PATH`] if it is not set).
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`SECURE_DEFAULT_PATH`]: ./constant.SECURE_DEFAULT_PATH.html
+pub fn secure_default_path() -> OsString {
+    crate::secure_getenv_os("PATH").unwrap_or_else(|| SECURE_DEFAULT_PATH.into())
+}
+
+/// Look up an executable by name, like the `which` command.
+///
+/// If `name` contains a `/`, it is treated as a path and checked directly. Otherwise, each
+/// directory in [`secure_default_path()`] is searched in order for a regular file with that name
+/// that has at least one execute bit set.
+///
+/// If [`is_secure()`] returns `true`, the `PATH` environment variable is ignored (see
+/// [`secure_default_path()`]), and any candidate that fails [`verify_trusted_path()`] (with a
+/// policy that only trusts `root`) is skipped. Otherwise, empty entries in `PATH` refer to the
+/// current directory, as usual.
+///
+/// [`is_secure()`]: ./fn.is_secure.html
+/// [`secure_default_path()`]: ./fn.secure_default_path.html
+/// [`verify_trusted_path()`]: ./fn.verify_trusted_path.html
+pub fn secure_which<S: AsRef<OsStr>>(name: S) -> Option<PathBuf> {
+    which_impl(name.as_ref(), &secure_default_path(), crate::is_secure())
+}
+
+fn which_impl(name: &OsStr, search_path: &OsStr, secure: bool) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let is_candidate = |path: &Path| {
+        let executable = std::fs::metadata(path)
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0);
+
+        executable && (!secure || verify_trusted_path(path, &TrustPolicy::new()).is_ok())
+    };
+
+    if name.as_bytes().contains(&b'/') {
+        let path = PathBuf::from(name);
+        return if is_candidate(&path) {
+            Some(path)
+        } else {
+            None
+        };
+    }
+
+    search_path
+        .as_bytes()
+        .split(|&ch| ch == b':')
+        .filter(|dir| !secure || !dir.is_empty())
+        .map(|dir| {
+            if dir.is_empty() {
+                Path::new(".").join(name)
+            } else {
+                Path::new(OsStr::from_bytes(dir)).join(name)
+            }
+        })
+        .find(|path| is_candidate(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_default_path() {
+        assert_eq!(
+            secure_default_path(),
+            std::env::var_os("PATH").unwrap_or_else(|| SECURE_DEFAULT_PATH.into())
+        );
+    }
+
+    #[test]
+    fn test_secure_which() {
+        let sh = secure_which("sh").unwrap();
+        assert!(sh.ends_with("sh"));
+        assert!(secure_which("secure_exec_nonexistent").is_none());
+        assert!(secure_which("").is_none());
+
+        let default = OsStr::new(SECURE_DEFAULT_PATH);
+        assert!(which_impl(OsStr::new("sh"), default, true).is_some());
+        assert_eq!(
+            which_impl(OsStr::new("/bin/sh"), default, true),
+            Some("/bin/sh".into())
+        );
+        assert!(which_impl(OsStr::new("/etc/passwd"), default, true).is_none());
+
+        let dir = crate::util::TempDir::new("which");
+        let prog = dir.path().join("secure_exec_prog");
+        std::fs::write(&prog, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&prog, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut path = dir.path().as_os_str().to_os_string();
+        path.push(":");
+        path.push(SECURE_DEFAULT_PATH);
+        let name = OsStr::new("secure_exec_prog");
+
+        assert_eq!(which_impl(name, &path, false), Some(prog));
+        // The candidate is under a world-writable directory
+        assert_eq!(which_impl(name, &path, true), None);
+        assert_eq!(which_impl(name, default, false), None);
+    }
+}
@@ -0,0 +1,25 @@
+#![cfg(feature = "constructor")]
+
+#[test]
+#[ignore = "must be run as root (to change the effective UID)"]
+fn test_constructor_captures_initial_state() {
+    assert_eq!(
+        unsafe { libc::geteuid() },
+        0,
+        "this test must be run as root"
+    );
+
+    // Change the effective UID before calling into the library for the first time. The
+    // constructor should already have recorded the original state.
+    assert_eq!(unsafe { libc::seteuid(65534) }, 0);
+
+    let creds = secure_exec::initial_credentials();
+    let secure = secure_exec::is_secure();
+    let reason = secure_exec::secure_reason();
+
+    assert_eq!(unsafe { libc::seteuid(0) }, 0);
+
+    assert_eq!(creds.euid(), 0);
+    assert!(!secure);
+    assert!(reason.is_empty());
+}